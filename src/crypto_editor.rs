@@ -1,3 +1,4 @@
+use std::io::Error;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
@@ -8,58 +9,94 @@ pub mod crypto_editor {
     pub mod row;
     pub mod terminal;
     pub use document::Document;
-    pub use editor::Editor;
-    pub use editor::EditorEvent;
-    pub use editor::EditorMessage;
     pub use editor::Position;
     pub use row::Row;
     pub use terminal::Terminal;
 }
 
 pub use crypto_editor::editor::*;
-pub use crypto_editor::*;
 
 pub struct CryptoEditor;
 
 pub use super::database::database_handler;
-pub use super::database::keychain;
+use super::encryption::crypto::crypto::LogPasswordKey;
+pub use super::encryption::session::session::Session;
 pub use database_handler::*;
-pub use keychain::*;
+use zeroize::Zeroizing;
 
 impl CryptoEditor {
-    pub fn edit_log(date: &str, user: &str, password: &str, log_pos: usize) {
-        let (tx, rx): (Sender<EditorMessage>, Receiver<EditorMessage>) = mpsc::channel();
-        let date_temp = String::from(date).chars().collect::<Vec<char>>().to_vec();
-        let user_temp = String::from(user).chars().collect::<Vec<char>>().to_vec();
-        let password_temp = String::from(password)
-            .chars()
-            .collect::<Vec<char>>()
-            .to_vec();
+    // Sealed logs are opened with their own password, and saved with the key
+    // it derives so that saving does not run the key derivation again.
+    pub fn edit_log(date: &str, session: &Session, log_id: &str, password: Option<&str>) {
+        let opened = match password {
+            Some(password) => logs_api::open_sealed_log(date, session, log_id, password)
+                .map(|(content, key)| (content, Some(key))),
+            None => logs_api::get_date_log(date, session, log_id).map(|content| (content, None)),
+        };
+        Self::edit_content(opened, date, session, log_id);
+    }
+
+    // Opens an earlier version of a log. Saving it makes the text, edited or
+    // not, the newest version of the log.
+    pub fn view_revision(
+        date: &str,
+        session: &Session,
+        log_id: &str,
+        revision: usize,
+        password: Option<&str>,
+    ) {
+        let opened = match password {
+            Some(password) => {
+                logs_api::open_sealed_revision(date, session, log_id, revision, password)
+                    .map(|(content, key)| (content, Some(key)))
+            }
+            None => logs_api::get_log_revision(date, session, log_id, revision, None)
+                .map(|content| (content, None)),
+        };
+        Self::edit_content(opened, date, session, log_id);
+    }
 
-        match logs_api::get_date_log(date, user, password, log_pos) {
-            Ok(content) => {
-                let child = thread::spawn(move || loop {
-                    let msg = &rx.recv().unwrap();
+    fn edit_content(
+        opened: Result<(Zeroizing<Vec<u8>>, Option<LogPasswordKey>), Error>,
+        date: &str,
+        session: &Session,
+        log_id: &str,
+    ) {
+        let (tx, rx): (Sender<EditorMessage>, Receiver<EditorMessage>) = mpsc::channel();
+        let (saved_tx, saved_rx) = mpsc::channel();
 
-                    match msg.event {
-                        EditorEvent::Save => {
-                            if let Err(_) = logs_api::overwrite_log(
-                                &date_temp.iter().cloned().collect::<String>(),
-                                &user_temp.iter().cloned().collect::<String>(),
-                                &msg.message,
-                                &password_temp.iter().cloned().collect::<String>(),
-                                log_pos,
-                            ) {
+        match opened {
+            Ok((content, key)) => {
+                thread::scope(|scope| {
+                    // A failed save is reported to the editor, which stays
+                    // open so that the log can be saved again.
+                    let child = scope.spawn(move || {
+                        while let Ok(msg) = rx.recv() {
+                            if let EditorEvent::Exit = msg.event {
+                                break;
+                            }
+                            let saved = match &key {
+                                Some(key) => logs_api::overwrite_sealed_log(
+                                    date,
+                                    session,
+                                    &msg.message,
+                                    log_id,
+                                    key,
+                                ),
+                                None => {
+                                    logs_api::overwrite_log(date, session, &msg.message, log_id)
+                                }
+                            };
+                            if saved_tx.send(saved).is_err() {
                                 break;
                             }
                         }
-                        EditorEvent::Exit => break,
-                    }
-                });
+                    });
 
-                Editor::open_log(content, date, tx).run();
+                    Editor::open_log(content, date, tx, saved_rx).run();
 
-                child.join().expect("oops! the child thread panicked");
+                    child.join().expect("oops! the child thread panicked");
+                });
             }
             Err(why) => {
                 println!("Unable to open log!");
@@ -68,50 +105,33 @@ impl CryptoEditor {
         };
     }
 
-    pub fn create_log(date: &str, user: &str, password: &str) {
+    // The log is inserted by the first save that succeeds and overwritten by
+    // the ones after it.
+    pub fn create_log(date: &str, session: &Session) {
         let (tx, rx): (Sender<EditorMessage>, Receiver<EditorMessage>) = mpsc::channel();
-        let date_temp = String::from(date).chars().collect::<Vec<char>>().to_vec();
-        let user_temp = String::from(user).chars().collect::<Vec<char>>().to_vec();
-        let password_temp = String::from(password)
-            .chars()
-            .collect::<Vec<char>>()
-            .to_vec();
+        let (saved_tx, saved_rx) = mpsc::channel();
         // EVALUATE IF LOG ALREADY EXISTS
-        let child = thread::spawn(move || {
-            let log_pos;
-            let msg = &rx.recv().unwrap();
-
-            match msg.event {
-                EditorEvent::Save => {
-                    log_pos = logs_api::insert_log(
-                        &date_temp.iter().cloned().collect::<String>(),
-                        &user_temp.iter().cloned().collect::<String>(),
-                        &msg.message,
-                        &password_temp.iter().cloned().collect::<String>(),
-                    )
-                    .expect("Unable to write a new log");
-
-                    loop {
-                        let msg = &rx.recv().unwrap();
-                        match msg.event {
-                            EditorEvent::Save => {
-                                logs_api::overwrite_log(
-                                    &date_temp.iter().cloned().collect::<String>(),
-                                    &user_temp.iter().cloned().collect::<String>(),
-                                    &msg.message,
-                                    &password_temp.iter().cloned().collect::<String>(),
-                                    log_pos,
-                                )
-                                .expect("Unable to write a new log");
-                            }
-                            EditorEvent::Exit => break,
+        thread::scope(|scope| {
+            let child = scope.spawn(move || {
+                let mut log_id: Option<String> = None;
+                while let Ok(msg) = rx.recv() {
+                    if let EditorEvent::Exit = msg.event {
+                        break;
+                    }
+                    let saved = match &log_id {
+                        Some(log_id) => {
+                            logs_api::overwrite_log(date, session, &msg.message, log_id)
                         }
+                        None => logs_api::insert_log(date, session, &msg.message)
+                            .map(|id| log_id = Some(id)),
+                    };
+                    if saved_tx.send(saved).is_err() {
+                        break;
                     }
                 }
-                EditorEvent::Exit => (),
-            }
+            });
+            Editor::default(tx, saved_rx).run();
+            child.join().expect("oops! the child thread panicked");
         });
-        Editor::default(tx).run();
-        child.join().expect("oops! the child thread panicked");
     }
 }
@@ -2,7 +2,8 @@ use super::Position;
 use super::Row;
 use std::fs;
 use std::io::Error;
-use std::string::FromUtf8Error;
+use std::str::Utf8Error;
+use zeroize::Zeroizing;
 
 #[derive(Default)]
 pub struct Document {
@@ -25,8 +26,8 @@ impl Document {
             dirty: false,
         })
     }
-    pub fn open_from_u8(characters: Vec<u8>, log_date: &str) -> Result<Self, FromUtf8Error> {
-        let contents = String::from_utf8(characters)?;
+    pub fn open_from_u8(characters: Zeroizing<Vec<u8>>, log_date: &str) -> Result<Self, Utf8Error> {
+        let contents = std::str::from_utf8(&characters)?;
         let mut rows = Vec::new();
         for value in contents.lines() {
             rows.push(Row::from(value));
@@ -56,7 +57,7 @@ impl Document {
         }
         #[allow(clippy::indexing_slicing)]
         let new_row = self.rows[at.y].split(at.x);
-        #[allow(clippy::integer_arithmetic)]
+        #[allow(clippy::arithmetic_side_effects)]
         self.rows.insert(at.y + 1, new_row);
     }
     pub fn insert(&mut self, at: &Position, c: char) {
@@ -78,7 +79,7 @@ impl Document {
             row.insert(at.x, c);
         }
     }
-    #[allow(clippy::integer_arithmetic, clippy::indexing_slicing)]
+    #[allow(clippy::arithmetic_side_effects, clippy::indexing_slicing)]
     pub fn delete(&mut self, at: &Position) {
         let len = self.rows.len();
         if at.y >= len {
@@ -95,17 +96,17 @@ impl Document {
         }
     }
     pub fn save(&mut self) -> Result<(), Error> {
-        if let Some(file_name) = &self.file_name {
-            Some(file_name);
+        if self.file_name.is_some() {
             self.dirty = false;
         }
         Ok(())
     }
-    pub fn to_string(&mut self) -> String {
-        let mut contents = String::new();
+    pub fn contents(&self) -> Zeroizing<String> {
+        let len = self.rows.iter().map(|row| row.as_str().len() + 1).sum();
+        let mut contents = Zeroizing::new(String::with_capacity(len));
         for row in &self.rows {
-            contents.push_str(&row.as_string());
-            contents.push_str("\n");
+            contents.push_str(row.as_str());
+            contents.push('\n');
         }
         contents
     }
@@ -3,11 +3,12 @@ use super::Document;
 use super::Row;
 use super::Terminal;
 use std::env;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender};
 use std::time::Duration;
 use std::time::Instant;
 use termion::color;
 use termion::event::Key;
+use zeroize::Zeroizing;
 
 const STATUS_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
@@ -27,7 +28,7 @@ pub enum EditorEvent {
 
 pub struct EditorMessage {
     pub event: EditorEvent,
-    pub message: String,
+    pub message: Zeroizing<String>,
 }
 
 struct StatusMessage {
@@ -53,6 +54,7 @@ pub struct Editor {
     status_message: StatusMessage,
     quit_times: u8,
     content_sender: Sender<EditorMessage>,
+    saved_receiver: Receiver<Result<(), std::io::Error>>,
 }
 
 impl Editor {
@@ -69,11 +71,14 @@ impl Editor {
             }
         }
     }
-    pub fn default(content_sender: Sender<EditorMessage>) -> Self {
+    pub fn default(
+        content_sender: Sender<EditorMessage>,
+        saved_receiver: Receiver<Result<(), std::io::Error>>,
+    ) -> Self {
         let args: Vec<String> = env::args().collect();
         let mut initial_status = String::from("HELP: Ctrl-S = save | Ctrl-Q = quit");
         let document = if let Some(file_name) = args.get(1) {
-            let doc = Document::open(&file_name);
+            let doc = Document::open(file_name);
             if let Ok(doc) = doc {
                 doc
             } else {
@@ -92,19 +97,21 @@ impl Editor {
             status_message: StatusMessage::from(initial_status),
             quit_times: QUIT_TIMES,
             content_sender,
+            saved_receiver,
         }
     }
     pub fn open_log(
-        read_rows: Vec<u8>,
+        read_rows: Zeroizing<Vec<u8>>,
         log_date: &str,
         content_sender: Sender<EditorMessage>,
+        saved_receiver: Receiver<Result<(), std::io::Error>>,
     ) -> Self {
         let mut initial_status = String::from("HELP: Ctrl-S = save | Ctrl-Q = quit");
         let document = Document::open_from_u8(read_rows, log_date);
         let document = if let Ok(document) = document {
             document
         } else {
-            initial_status = format!("ERR: Could not open file");
+            initial_status = String::from("ERR: Could not open file");
             Document::default()
         };
 
@@ -117,6 +124,7 @@ impl Editor {
             status_message: StatusMessage::from(initial_status),
             quit_times: QUIT_TIMES,
             content_sender,
+            saved_receiver,
         }
     }
     fn process_keypress(&mut self) -> Result<(), std::io::Error> {
@@ -134,34 +142,42 @@ impl Editor {
 
                 let message = EditorMessage {
                     event: EditorEvent::Exit,
-                    message: String::new(),
+                    message: Zeroizing::default(),
                 };
-                self.content_sender.send(message).unwrap();
+                let _ = self.content_sender.send(message);
                 self.should_quit = true
             }
             Key::Ctrl('s') => {
                 let edit_message = EditorMessage {
                     event: EditorEvent::Save,
-                    message: self.document.to_string(),
+                    message: self.document.contents(),
+                };
+                // Whoever receives the contents saves them and answers with
+                // how that went.
+                let closed = || String::from("the log is no longer open");
+                let saved = self
+                    .content_sender
+                    .send(edit_message)
+                    .map_err(|_| closed())
+                    .and_then(|()| match self.saved_receiver.recv() {
+                        Ok(saved) => saved.map_err(|why| why.to_string()),
+                        Err(_) => Err(closed()),
+                    });
+                self.status_message = match saved
+                    .and_then(|()| self.document.save().map_err(|why| why.to_string()))
+                {
+                    Ok(()) => StatusMessage::from("File saved successfully.".to_string()),
+                    Err(why) => StatusMessage::from(format!("Error writing file: {}", why)),
                 };
-                self.content_sender.send(edit_message).unwrap();
-                if self.document.save().is_ok() {
-                    self.status_message =
-                        StatusMessage::from("File saved successfully.".to_string())
-                } else {
-                    self.status_message = StatusMessage::from("Error writing file!".to_string());
-                }
             }
             Key::Char(c) => {
                 self.document.insert(&self.cursor_position, c);
                 self.move_cursor(Key::Right);
             }
             Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
-                }
+            Key::Backspace if self.cursor_position.x > 0 || self.cursor_position.y > 0 => {
+                self.move_cursor(Key::Left);
+                self.document.delete(&self.cursor_position);
             }
             Key::Up
             | Key::Down
@@ -184,7 +200,7 @@ impl Editor {
         let Position { x, y } = self.cursor_position;
         let width = self.terminal.size().width as usize;
         let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
         } else if y >= offset.y.saturating_add(height) {
@@ -207,11 +223,7 @@ impl Editor {
         };
         match key {
             Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1)
-                }
-            }
+            Key::Down if y < height => y = y.saturating_add(1),
             Key::Left => {
                 if x > 0 {
                     x -= 1;
@@ -289,14 +301,14 @@ impl Editor {
         let mut welcome_message = format!("Minimal text editor -- version {}\r", VERSION);
         let width = self.terminal.size().width as usize;
         let len = welcome_message.len();
-        #[allow(clippy::integer_arithmetic, clippy::integer_division)]
+        #[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
         let padding = width.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
         welcome_message = format!("~{}{}", spaces, welcome_message);
         welcome_message.truncate(width);
         println!("{}\r", welcome_message);
     }
-    #[allow(clippy::integer_division, clippy::integer_arithmetic)]
+    #[allow(clippy::integer_division, clippy::arithmetic_side_effects)]
     fn draw_rows(&self) {
         let height = self.terminal.size().height;
         for terminal_row in 0..height {
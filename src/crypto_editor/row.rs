@@ -1,27 +1,34 @@
 use std::cmp;
 use unicode_segmentation::UnicodeSegmentation;
+use zeroize::Zeroizing;
 
+// Rows hold decrypted text, so every buffer a row lets go of is wiped: edits
+// build the new text in a buffer sized up front and replace the old one.
 #[derive(Default)]
 pub struct Row {
-    string: String,
+    string: Zeroizing<String>,
     len: usize,
 }
 
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
         Self {
-            string: String::from(slice),
+            string: Zeroizing::new(String::from(slice)),
             len: slice.graphemes(true).count(),
         }
     }
 }
 
+fn with_capacity(capacity: usize) -> Zeroizing<String> {
+    Zeroizing::new(String::with_capacity(capacity))
+}
+
 impl Row {
     pub fn render(&self, start: usize, end: usize) -> String {
         let end = cmp::min(end, self.string.len());
         let start = cmp::min(start, end);
         let mut result = String::new();
-        #[allow(clippy::integer_arithmetic)]
+        #[allow(clippy::arithmetic_side_effects)]
         for grapheme in self.string[..]
             .graphemes(true)
             .skip(start)
@@ -42,11 +49,16 @@ impl Row {
 
     pub fn insert(&mut self, at: usize, c: char) {
         if at >= self.len() {
+            if self.string.capacity() - self.string.len() < c.len_utf8() {
+                let mut grown = with_capacity(2 * self.string.len() + c.len_utf8());
+                grown.push_str(&self.string);
+                self.string = grown;
+            }
             self.string.push(c);
             self.len += 1;
             return;
         }
-        let mut result: String = String::new();
+        let mut result = with_capacity(self.string.len() + c.len_utf8());
         let mut length = 0;
         for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
             length += 1;
@@ -60,13 +72,13 @@ impl Row {
         self.string = result;
     }
 
-    #[allow(clippy::integer_arithmetic)]
+    #[allow(clippy::arithmetic_side_effects)]
     pub fn delete(&mut self, at: usize) {
         if at >= self.len() {
             return;
         }
 
-        let mut result: String = String::new();
+        let mut result = with_capacity(self.string.len());
         let mut length = 0;
         for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
             if index != at {
@@ -78,13 +90,16 @@ impl Row {
         self.string = result;
     }
     pub fn append(&mut self, new: &Self) {
-        self.string = format!("{}{}", self.string, new.string);
+        let mut result = with_capacity(self.string.len() + new.string.len());
+        result.push_str(&self.string);
+        result.push_str(&new.string);
+        self.string = result;
         self.len += new.len;
     }
     pub fn split(&mut self, at: usize) -> Self {
-        let mut row: String = String::new();
+        let mut row = with_capacity(self.string.len());
         let mut length = 0;
-        let mut splitted_row: String = String::new();
+        let mut splitted_row = with_capacity(self.string.len());
         let mut splitted_length = 0;
         for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
             if index < at {
@@ -103,7 +118,7 @@ impl Row {
         }
     }
 
-    pub fn as_string(&self) -> String {
-        self.string.clone()
+    pub fn as_str(&self) -> &str {
+        &self.string
     }
 }
@@ -1,111 +1,129 @@
 pub mod logs_api {
+    use crate::database::index::log_index::{self, Entry, Index};
+    use crate::database::keychain::key_chain;
+    use crate::database::manifest::log_manifest::{self, Manifest};
+    use crate::database::storage::storage::Database;
+    use crate::database::throttle::throttle::{Record, Status};
+    use crate::encryption::container::container::{LogIdentity, HEADER_SIZE};
+    use crate::encryption::crypto::crypto::LogPasswordKey;
     use crate::encryption::crypto::*;
+    use crate::encryption::kdf_policy::kdf_policy::KdfPolicy;
+    use crate::encryption::session::session::Session;
+    use crate::encryption::signing::signing;
+    use crate::encryption::stream::stream::{self, StreamWriter};
     use crate::utils::utils::file_system;
-    use std::env;
-    use std::fs;
-    use std::fs::OpenOptions;
     use std::io::prelude::*;
+    use std::io::BufReader;
     use std::io::{Error, ErrorKind};
+    use zeroize::Zeroizing;
 
-    #[derive(Debug)]
     pub struct Log {
-        pub content: String,
+        pub content: Zeroizing<String>,
     }
 
-    pub fn get_key_ids() -> Result<Vec<String>, Error> {
-        match env::var("DATABASE_IDS_DIR") {
-            Ok(ids_dir) => {
-                let ids = file_system::read_dir_to_string(ids_dir)?;
-                Ok(ids)
-            }
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                "DATABASE_IDS_DIR env variable Not Found",
-            )),
-        }
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct LogSummary {
+        pub id: String,
+        pub sealed: bool,
+        pub saved: Option<u64>,
     }
 
-    pub fn has_logs(id: &str) -> bool {
-        if let Ok(_) = get_years(id) {
-            return true;
-        }
-
-        false
+    pub fn get_key_ids(db: &Database) -> Result<Vec<String>, Error> {
+        Ok(db
+            .storage()
+            .list(db.ids_dir())?
+            .into_iter()
+            .filter(|name| !name.starts_with('.'))
+            .collect())
     }
 
-    pub fn get_years(id: &str) -> Result<Vec<String>, Error> {
-        match env::var("DATABASE_IDS_DIR") {
-            Ok(db_dir) => {
-                let mut dates = file_system::read_dir_to_string(db_dir + "/" + id)?;
-                dates.sort_by(|a, b| {
-                    a.parse::<i32>()
-                        .unwrap()
-                        .partial_cmp(&b.parse::<i32>().unwrap())
-                        .unwrap()
-                });
-                Ok(dates)
-            }
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                "DATABASE_IDS_DIR env variable Not Found",
-            )),
+    pub fn has_logs(session: &Session) -> bool {
+        match log_index::load(session) {
+            Ok(index) => !index.entries.is_empty(),
+            Err(_) => false,
         }
     }
 
-    pub fn get_months(id: &str, year: &str) -> Result<Vec<String>, Error> {
-        match env::var("DATABASE_IDS_DIR") {
-            Ok(db_dir) => {
-                let mut dates = file_system::read_dir_to_string(db_dir + "/" + id + "/" + year)?;
-                dates.sort_by(|a, b| {
-                    a.parse::<i32>()
-                        .unwrap()
-                        .partial_cmp(&b.parse::<i32>().unwrap())
-                        .unwrap()
-                });
-                Ok(dates)
+    pub fn get_years(session: &Session) -> Result<Vec<String>, Error> {
+        let index = log_index::load(session)?;
+        Ok(collect_dates(&index, |date| Some(date[2])))
+    }
+
+    pub fn get_months(session: &Session, year: &str) -> Result<Vec<String>, Error> {
+        let index = log_index::load(session)?;
+        Ok(collect_dates(&index, |date| {
+            if date[2] == year {
+                Some(date[1])
+            } else {
+                None
             }
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                "DATABASE_IDS_DIR env variable Not Found",
-            )),
-        }
+        }))
     }
 
-    pub fn get_days(id: &str, year: &str, month: &str) -> Result<Vec<String>, Error> {
-        match env::var("DATABASE_IDS_DIR") {
-            Ok(db_dir) => {
-                let mut dates =
-                    file_system::read_dir_to_string(db_dir + "/" + id + "/" + year + "/" + month)?;
-                dates.sort_by(|a, b| {
-                    a.parse::<i32>()
-                        .unwrap()
-                        .partial_cmp(&b.parse::<i32>().unwrap())
-                        .unwrap()
-                });
-                Ok(dates)
+    pub fn get_days(session: &Session, year: &str, month: &str) -> Result<Vec<String>, Error> {
+        let index = log_index::load(session)?;
+        Ok(collect_dates(&index, |date| {
+            if date[2] == year && date[1] == month {
+                Some(date[0])
+            } else {
+                None
             }
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                "DATABASE_IDS_DIR env variable Not Found",
-            )),
-        }
+        }))
     }
 
+    // The logs of a day in the order they were written.
     pub fn get_day_logs(
-        id: &str,
+        session: &Session,
         year: &str,
         month: &str,
         day: &str,
-    ) -> Result<Vec<String>, Error> {
-        let dates = file_system::read_dir_to_string(get_user_logs_dir(
-            &format!("{}_{}_{}", day, month, year),
-            id,
-        )?)?;
-        Ok(dates)
+    ) -> Result<Vec<LogSummary>, Error> {
+        let index = log_index::load(session)?;
+        let date = format!("{}_{}_{}", day, month, year);
+        Ok(index
+            .day(&date)
+            .iter()
+            .map(|entry| LogSummary {
+                id: entry.id.clone(),
+                sealed: entry.sealed,
+                saved: entry.saved,
+            })
+            .collect())
+    }
+
+    // Picks one part of every indexed "dd_mm_yyyy" date, without duplicates
+    // and in numeric order.
+    fn collect_dates<'a, F>(index: &'a Index, select: F) -> Vec<String>
+    where
+        F: Fn(&[&'a str]) -> Option<&'a str>,
+    {
+        let mut dates: Vec<String> = Vec::new();
+        for entry in index.entries.iter() {
+            let date: Vec<&str> = entry.date.split('_').collect();
+            if date.len() != 3 {
+                continue;
+            }
+            if let Some(part) = select(&date) {
+                if !dates.iter().any(|known| known == part) {
+                    dates.push(part.to_string());
+                }
+            }
+        }
+        dates.sort_by_key(|part| part.parse::<i32>().unwrap_or(0));
+        dates
+    }
+
+    pub fn get_user_log_locations(session: &Session) -> Result<Vec<(String, String)>, Error> {
+        Ok(log_index::load(session)?
+            .entries
+            .into_iter()
+            .map(|entry| (entry.date, entry.id))
+            .collect())
     }
 
-    pub fn get_user_logs_dir(date: &str, user: &str) -> Result<String, Error> {
-        let db_dir = file_system::get_env_var("DATABASE_IDS_DIR")?;
+    // The directory a log of the given "dd_mm_yyyy" date was written to before
+    // logs were stored as blobs.
+    pub fn get_user_logs_dir(ids_dir: &str, date: &str, user: &str) -> Result<String, Error> {
         let split_date: Vec<&str> = date.split('_').collect();
         if split_date.len() == 3 {
             let d = split_date[0];
@@ -113,80 +131,376 @@ pub mod logs_api {
             let y = split_date[2];
 
             let file_path =
-                file_system::generate_path(&[&db_dir, "/", &user, "/", &y, "/", m, "/", d]);
+                file_system::generate_path(&[ids_dir, "/", user, "/", y, "/", m, "/", d]);
             Ok(file_path)
         } else {
-            Err(Error::new(ErrorKind::Other, "Invalid date"))
+            Err(Error::other("Invalid date"))
         }
     }
 
-    pub fn write_log(file_path: &str, ciphertext: &[u8]) -> Result<(), Error> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(file_path)?;
+    // Logs above this size are sealed as a stream of chunks.
+    pub const STREAM_THRESHOLD: usize = 1 << 20;
 
-        file.write(ciphertext)?;
+    pub fn write_log(
+        session: &Session,
+        identity: &LogIdentity,
+        plaintext: &[u8],
+        file_path: &str,
+    ) -> Result<(), Error> {
+        let storage = session.db().storage();
+        if plaintext.len() <= STREAM_THRESHOLD {
+            let ciphertext = crypto::encrypt_with_master_key(
+                session.db(),
+                session.master_key(),
+                identity,
+                plaintext,
+            )
+            .map_err(Error::other)?;
+            return storage.write(file_path, &ciphertext);
+        }
 
-        Ok(())
+        write_log_from(session, identity, &mut &plaintext[..], file_path)
     }
 
-    pub fn insert_log(
+    // Seals whatever `reader` yields as a stream, a chunk at a time, so the
+    // plaintext never has to be in memory whole. Backends that seal a file as
+    // one record, like the vault, still buffer the ciphertext.
+    pub fn write_log_from<R: Read>(
+        session: &Session,
+        identity: &LogIdentity,
+        reader: &mut R,
+        file_path: &str,
+    ) -> Result<(), Error> {
+        session.db().storage().write_with(file_path, &mut |writer| {
+            stream::encrypt_stream(session.master_key(), identity, reader, writer)
+        })
+    }
+
+    // Opens a log into `writer`. A stream is opened a chunk at a time; on an
+    // error, what was written so far has to be discarded.
+    pub fn read_log_into<W: Write + ?Sized>(
+        session: &Session,
+        identity: &LogIdentity,
+        file_path: &str,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        let storage = session.db().storage();
+        let opened = if stream::is_stream(&read_header(session, file_path)?) {
+            let reader = storage.reader(file_path)?;
+            stream::decrypt_stream(
+                session.master_key(),
+                identity,
+                &mut BufReader::new(reader),
+                writer,
+            )
+        } else {
+            open_single(session, identity, file_path).and_then(|content| writer.write_all(&content))
+        };
+
+        opened.map_err(|_| modified_log_error(identity))
+    }
+
+    fn open_single(
+        session: &Session,
+        identity: &LogIdentity,
+        file_path: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let content = session.db().storage().read(file_path)?;
+        crypto::decrypt_str(session, identity, &content)
+            .map(Zeroizing::new)
+            .map_err(Error::other)
+    }
+
+    fn modified_log_error(identity: &LogIdentity) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Integrity check failed: log {} of {} was modified, moved or does not belong to {}",
+                identity.log_id, identity.date, identity.user
+            ),
+        )
+    }
+
+    // The blob is written before the index refers to it, so an interrupted
+    // insert leaves at most an unreferenced blob behind.
+    pub fn insert_log(date: &str, session: &Session, message: &str) -> Result<String, Error> {
+        let mut index = log_index::load(session)?;
+        let id = log_index::generate_log_id()?;
+
+        let (blob, signature) = write_blob(session, date, &id, message.as_bytes(), None)?;
+
+        index.entries.push(Entry {
+            date: date.to_string(),
+            id: id.clone(),
+            blob,
+            signature: Some(signature),
+            sealed: false,
+            saved: Some(file_system::unix_time()),
+        });
+        log_index::save(session, &index)?;
+        update_manifest(session, &index, &[(date, &id)], &[])?;
+
+        Ok(id)
+    }
+
+    pub fn overwrite_log(
         date: &str,
-        user: &str,
+        session: &Session,
         message: &str,
-        password: &str,
-    ) -> Result<usize, Error> {
-        let db_dir = get_user_logs_dir(&date, &user)?;
-        fs::create_dir_all(db_dir.clone())?;
-        let previous_logs = file_system::read_dir_to_string(db_dir.clone())?;
-        let file_path = file_system::generate_log_path(&db_dir, previous_logs.len());
-        let ciphertext = match crypto::encrypt_str(user, message, password) {
-            Err(why) => Err(Error::new(ErrorKind::Other, why)),
-            Ok(text) => Ok(text),
-        }?;
+        id: &str,
+    ) -> Result<(), Error> {
+        save_log(date, session, id, message.as_bytes(), None)
+    }
+
+    // Overwrites a sealed log with the key `open_sealed_log` returned, so it
+    // keeps its password without deriving the key again.
+    pub fn overwrite_sealed_log(
+        date: &str,
+        session: &Session,
+        message: &str,
+        id: &str,
+        key: &LogPasswordKey,
+    ) -> Result<(), Error> {
+        save_log(date, session, id, message.as_bytes(), Some(key))
+    }
 
-        let ciphertext = unsafe { String::from_utf8_unchecked(ciphertext) };
+    pub fn is_sealed(date: &str, session: &Session, id: &str) -> Result<bool, Error> {
+        Ok(find_entry(&log_index::load(session)?, date, id)?.sealed)
+    }
 
-        match write_log(&file_path, ciphertext.as_bytes()) {
-            Ok(_) => Ok(previous_logs.len()),
-            Err(err) => Err(err),
+    // Locks a log with a password of its own on top of the journal's keys.
+    pub fn seal_log(date: &str, session: &Session, id: &str, password: &str) -> Result<(), Error> {
+        let mut index = log_index::load(session)?;
+        if find_entry(&index, date, id)?.sealed {
+            return Err(sealed_error(date, id));
         }
+        let params = KdfPolicy::from_env().calibrate().map_err(Error::other)?;
+        let key = LogPasswordKey::new(password, &params).map_err(Error::other)?;
+
+        set_log_password(session, &mut index, date, id, None, Some(&key))
     }
 
-    pub fn overwrite_log(
+    pub fn unseal_log(
         date: &str,
-        user: &str,
-        message: &str,
+        session: &Session,
+        id: &str,
         password: &str,
-        log_pos: usize,
     ) -> Result<(), Error> {
-        let db_dir = get_user_logs_dir(&date, &user)?;
-        fs::create_dir_all(db_dir.clone())?;
-        let file_path = file_system::generate_log_path(&db_dir, log_pos);
-        let ciphertext = match crypto::encrypt_str(user, message, password) {
-            Err(why) => Err(Error::new(ErrorKind::Other, why)),
-            Ok(text) => Ok(text),
-        }?;
+        let mut index = log_index::load(session)?;
+        // Checks the password before anything is rewritten.
+        open_sealed_entry(session, find_entry(&index, date, id)?, password)?;
+
+        set_log_password(session, &mut index, date, id, Some(password), None)
+    }
 
-        let ciphertext = unsafe { String::from_utf8_unchecked(ciphertext) };
+    // Save times of the earlier versions of a log, oldest first. Versions
+    // saved before revisions were kept have none.
+    pub fn get_log_revisions(
+        date: &str,
+        session: &Session,
+        id: &str,
+    ) -> Result<Vec<Option<u64>>, Error> {
+        let index = log_index::load(session)?;
+        find_entry(&index, date, id)?;
 
-        write_log(&file_path, ciphertext.as_bytes())
+        Ok(index
+            .revisions(date, id)
+            .iter()
+            .map(|revision| revision.saved)
+            .collect())
     }
 
-    pub fn get_date_history_logs(
+    // Opens an earlier version of a log, counted as `get_log_revisions` lists
+    // them. The versions of a sealed log take its password.
+    pub fn get_log_revision(
         date: &str,
-        user: &str,
+        session: &Session,
+        id: &str,
+        revision: usize,
+        password: Option<&str>,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let index = log_index::load(session)?;
+        let entry = find_revision(&index, date, id, revision)?;
+        match password {
+            Some(password) => open_sealed_entry(session, entry, password).map(|opened| opened.0),
+            None if entry.sealed => Err(sealed_error(date, id)),
+            None => read_entry(session, entry),
+        }
+    }
+
+    // Opens an earlier version of a sealed log along with the key its password
+    // derives, which also opens and seals the newest version.
+    pub fn open_sealed_revision(
+        date: &str,
+        session: &Session,
+        id: &str,
+        revision: usize,
         password: &str,
-    ) -> Result<Vec<Log>, Error> {
-        let db_dir = get_user_logs_dir(&date, &user)?;
-        let previous_logs = file_system::read_dir_to_string(db_dir.clone())?;
+    ) -> Result<(Zeroizing<Vec<u8>>, LogPasswordKey), Error> {
+        let index = log_index::load(session)?;
+        open_sealed_entry(
+            session,
+            find_revision(&index, date, id, revision)?,
+            password,
+        )
+    }
+
+    // Saves an earlier version as the newest one, so the version it replaces
+    // is kept as well.
+    pub fn restore_revision(
+        date: &str,
+        session: &Session,
+        id: &str,
+        revision: usize,
+        password: Option<&str>,
+    ) -> Result<(), Error> {
+        match password {
+            Some(password) => {
+                let (content, key) = open_sealed_revision(date, session, id, revision, password)?;
+                save_log(date, session, id, &content, Some(&key))
+            }
+            None => {
+                let content = get_log_revision(date, session, id, revision, None)?;
+                save_log(date, session, id, &content, None)
+            }
+        }
+    }
+
+    // Saves a new version of a log and keeps the one it replaces. A sealed log
+    // takes the key of its password, which also seals the new version.
+    fn save_log(
+        date: &str,
+        session: &Session,
+        id: &str,
+        content: &[u8],
+        key: Option<&LogPasswordKey>,
+    ) -> Result<(), Error> {
+        let mut index = log_index::load(session)?;
+        let entry = find_entry(&index, date, id)?;
+        match key {
+            // Only a key that opens the version being replaced may seal the
+            // next one.
+            Some(key) => {
+                open_sealed_entry_with_key(session, entry, key)?;
+            }
+            None if entry.sealed => return Err(sealed_error(date, id)),
+            None => (),
+        }
+        let (blob, signature) = write_blob(session, date, id, content, key)?;
+
+        if let Some(entry) = index.find_mut(date, id) {
+            let replaced = std::mem::replace(
+                entry,
+                Entry {
+                    date: date.to_string(),
+                    id: id.to_string(),
+                    blob,
+                    signature: Some(signature),
+                    sealed: key.is_some(),
+                    saved: Some(file_system::unix_time()),
+                },
+            );
+            index.revisions.push(replaced);
+        }
+        log_index::save(session, &index)?;
+        update_manifest(session, &index, &[(date, id)], &[])
+    }
+
+    // Seals or unseals a log together with all its earlier versions, so none
+    // of them stays readable without the password. Every version is written
+    // to a new blob, and the old blobs are removed once the index no longer
+    // refers to them. Versions sealed with the same key only derive it once.
+    fn set_log_password(
+        session: &Session,
+        index: &mut Index,
+        date: &str,
+        id: &str,
+        current: Option<&str>,
+        new: Option<&LogPasswordKey>,
+    ) -> Result<(), Error> {
+        let mut replaced = Vec::new();
+        let mut current_key = None;
+        let versions = index
+            .entries
+            .iter_mut()
+            .chain(index.revisions.iter_mut())
+            .filter(|entry| entry.date == date && entry.id == id);
+        for entry in versions {
+            let content = match current {
+                Some(password) => {
+                    match current_key
+                        .as_ref()
+                        .and_then(|key| open_sealed_entry_with_key(session, entry, key).ok())
+                    {
+                        Some(content) => content,
+                        None => {
+                            let (content, key) = open_sealed_entry(session, entry, password)?;
+                            current_key = Some(key);
+                            content
+                        }
+                    }
+                }
+                None => read_entry(session, entry)?,
+            };
+            let (blob, signature) = write_blob(session, date, id, &content, new)?;
+
+            replaced.push(std::mem::replace(&mut entry.blob, blob));
+            entry.signature = Some(signature);
+            entry.sealed = new.is_some();
+        }
+        log_index::save(session, index)?;
+        update_manifest(session, index, &[(date, id)], &[])?;
+
+        let storage = session.db().storage();
+        for blob in replaced.iter() {
+            storage.delete(&blob_path(session, blob))?;
+        }
+        Ok(())
+    }
+
+    // Seals `content` as log `id` of `date` into a new blob, sealing it with
+    // the key of its own password first when one is given. Returns the blob's
+    // name and the signature, which covers what the master key seals: the
+    // text, or the inner layer of a sealed log, so that it cannot be used to
+    // confirm guesses of the text without the log's password.
+    fn write_blob(
+        session: &Session,
+        date: &str,
+        id: &str,
+        content: &[u8],
+        key: Option<&LogPasswordKey>,
+    ) -> Result<(String, Vec<u8>), Error> {
+        let blob = log_index::generate_blob_name()?;
+        let blob_path = blob_path(session, &blob);
+        let identity = session.identity(date, id);
+        let signing_key = key_chain::get_signing_key(session)?;
+        let signature = match key {
+            Some(key) => {
+                let sealed = crypto::seal_with_password(session.db(), &identity, key, content)
+                    .map_err(Error::other)?;
+                write_log(session, &identity, &sealed, &blob_path)?;
+                signing::sign(&signing_key, &identity, &sealed)
+            }
+            None => {
+                write_log(session, &identity, content, &blob_path)?;
+                signing::sign(&signing_key, &identity, content)
+            }
+        };
+
+        Ok((blob, signature))
+    }
+
+    pub fn get_date_history_logs(date: &str, session: &Session) -> Result<Vec<Log>, Error> {
+        let index = log_index::load(session)?;
         let mut contents = Vec::new();
-        for i in 0..previous_logs.len() {
-            let log_path = file_system::generate_log_path(&db_dir, i);
-            match String::from_utf8(decrypt_log(user, password, &log_path)?) {
+        for entry in index.day(date) {
+            if entry.sealed {
+                continue;
+            }
+            let mut decrypted = read_entry(session, entry)?;
+            match String::from_utf8(std::mem::take(&mut *decrypted)) {
                 Ok(decrypted_content) => contents.push(Log {
-                    content: decrypted_content,
+                    content: Zeroizing::new(decrypted_content),
                 }),
                 Err(_) => println!("Unable to decrypt message"),
             };
@@ -197,27 +511,719 @@ pub mod logs_api {
 
     pub fn get_date_log(
         date: &str,
-        user: &str,
+        session: &Session,
+        id: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let index = log_index::load(session)?;
+        let entry = find_entry(&index, date, id)?;
+        if entry.sealed {
+            return Err(sealed_error(date, id));
+        }
+
+        read_entry(session, entry)
+    }
+
+    pub fn get_sealed_log(
+        date: &str,
+        session: &Session,
+        id: &str,
+        password: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        open_sealed_log(date, session, id, password).map(|opened| opened.0)
+    }
+
+    // Opens a sealed log and returns the key its password derives as well,
+    // for saving it again with `overwrite_sealed_log`.
+    pub fn open_sealed_log(
+        date: &str,
+        session: &Session,
+        id: &str,
+        password: &str,
+    ) -> Result<(Zeroizing<Vec<u8>>, LogPasswordKey), Error> {
+        let index = log_index::load(session)?;
+        open_sealed_entry(session, find_entry(&index, date, id)?, password)
+    }
+
+    fn find_entry<'a>(index: &'a Index, date: &str, id: &str) -> Result<&'a Entry, Error> {
+        index
+            .find(date, id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No log {} on {}", id, date)))
+    }
+
+    fn find_revision<'a>(
+        index: &'a Index,
+        date: &str,
+        id: &str,
+        revision: usize,
+    ) -> Result<&'a Entry, Error> {
+        find_entry(index, date, id)?;
+        index
+            .revisions(date, id)
+            .get(revision)
+            .copied()
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("Log {} of {} has no revision {}", id, date, revision + 1),
+                )
+            })
+    }
+
+    fn sealed_error(date: &str, id: &str) -> Error {
+        Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Log {} of {} is sealed with its own password", id, date),
+        )
+    }
+
+    // Returns the content of a sealed log and the key its password derives.
+    // Wrong passwords are throttled like logins, see `throttle`.
+    fn open_sealed_entry(
+        session: &Session,
+        entry: &Entry,
         password: &str,
-        pos: usize,
-    ) -> Result<Vec<u8>, Error> {
-        let db_dir = get_user_logs_dir(&date, &user)?;
-        let log_path = file_system::generate_log_path(&db_dir, pos);
-        decrypt_log(user, password, &log_path)
-    }
-
-    pub fn decrypt_log(user: &str, password: &str, file_path: &str) -> Result<Vec<u8>, Error> {
-        let mut file = OpenOptions::new().read(true).open(file_path)?;
-        let mut content = vec![];
-
-        file.read_to_end(&mut content)?;
-        let decrypted_message;
-        unsafe {
-            decrypted_message =
-                crypto::decrypt_str(user, &String::from_utf8_unchecked(content), password)
-                    .expect("Error in decryption");
+    ) -> Result<(Zeroizing<Vec<u8>>, LogPasswordKey), Error> {
+        let sealed = read_sealed_entry(session, entry)?;
+        let record = Record::log_passwords(session.db(), session.user());
+        if record.status() != Status::Open {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Too many wrong log passwords, try again later",
+            ));
+        }
+        let key = LogPasswordKey::for_sealed(password, &sealed).map_err(Error::other)?;
+        let identity = session.identity(&entry.date, &entry.id);
+        let content = match crypto::open_with_password(&identity, &key, &sealed) {
+            Ok(content) => {
+                record.reset()?;
+                content
+            }
+            Err(_) => {
+                record.record_failure()?;
+                return Err(wrong_password_error(entry));
+            }
         };
+        verify_sealed_signature(session, entry, &sealed, &content)?;
+
+        Ok((content, key))
+    }
+
+    fn open_sealed_entry_with_key(
+        session: &Session,
+        entry: &Entry,
+        key: &LogPasswordKey,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let sealed = read_sealed_entry(session, entry)?;
+        let identity = session.identity(&entry.date, &entry.id);
+        let content = crypto::open_with_password(&identity, key, &sealed)
+            .map_err(|_| wrong_password_error(entry))?;
+        verify_sealed_signature(session, entry, &sealed, &content)?;
+
+        Ok(content)
+    }
 
-        Ok(decrypted_message)
+    fn read_sealed_entry(session: &Session, entry: &Entry) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if !entry.sealed {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Log {} of {} is not sealed", entry.id, entry.date),
+            ));
+        }
+
+        read_entry(session, entry)
+    }
+
+    fn wrong_password_error(entry: &Entry) -> Error {
+        Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Wrong password for log {} of {}", entry.id, entry.date),
+        )
+    }
+
+    // The signature of a sealed log covers its inner layer. Logs sealed before
+    // that was so carry a signature of their text until they are saved again.
+    fn verify_sealed_signature(
+        session: &Session,
+        entry: &Entry,
+        sealed: &[u8],
+        content: &[u8],
+    ) -> Result<(), Error> {
+        let identity = session.identity(&entry.date, &entry.id);
+        verify_signature(session, entry, &identity, sealed)
+            .or_else(|_| verify_signature(session, entry, &identity, content))
+    }
+
+    // Opens the blob of a log. For a sealed log that leaves the inner layer,
+    // whose signature is checked once it is opened.
+    fn read_entry(session: &Session, entry: &Entry) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let log_path = blob_path(session, &entry.blob);
+        let identity = session.identity(&entry.date, &entry.id);
+        let content = decrypt_log(session, &identity, &log_path)?;
+
+        if !entry.sealed {
+            verify_signature(session, entry, &identity, &content)?;
+        }
+        Ok(content)
+    }
+
+    // Logs saved before signing was added are only checked by the AEAD tag.
+    fn verify_signature(
+        session: &Session,
+        entry: &Entry,
+        identity: &LogIdentity,
+        content: &[u8],
+    ) -> Result<(), Error> {
+        if let Some(signature) = &entry.signature {
+            let signing_key = key_chain::get_signing_key(session)?;
+            signing::verify(signing_key.public_key(), identity, content, signature).map_err(
+                |_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Integrity check failed: the signature of log {} of {} does not match",
+                            entry.id, entry.date
+                        ),
+                    )
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn blob_path(session: &Session, blob: &str) -> String {
+        file_system::generate_blob_path(session.db().ids_dir(), session.user(), blob)
+    }
+
+    fn get_blob_path(
+        session: &Session,
+        index: &Index,
+        date: &str,
+        id: &str,
+    ) -> Result<String, Error> {
+        match index.find(date, id) {
+            Some(entry) => Ok(blob_path(session, &entry.blob)),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No log {} on {}", id, date),
+            )),
+        }
+    }
+
+    // Re-seals every log that is still bound to the password-derived key, or
+    // written before the log identity was authenticated, under the master key
+    // so the password can change without losing access. Each log is replaced
+    // atomically and stays readable with the current password whether or not
+    // it has been converted yet.
+    pub fn rekey_logs(session: &Session) -> Result<usize, Error> {
+        reseal_logs(session, |content| {
+            crypto::needs_rekey(content).map_err(Error::other)
+        })
+    }
+
+    // Re-seals every log with the current compression and padding settings.
+    pub fn recompress_logs(session: &Session) -> Result<usize, Error> {
+        reseal_logs(session, |_| Ok(true))
+    }
+
+    fn reseal_logs<F>(session: &Session, needs_reseal: F) -> Result<usize, Error>
+    where
+        F: Fn(&[u8]) -> Result<bool, Error>,
+    {
+        let index = log_index::load(session)?;
+        let mut resealed = Vec::new();
+        for entry in index.entries.iter() {
+            if reseal_blob(session, entry, &needs_reseal)? {
+                resealed.push((entry.date.as_str(), entry.id.as_str()));
+            }
+        }
+        // Earlier versions keep their blob, so the manifest is told which
+        // ones changed.
+        let mut resealed_revisions = Vec::new();
+        for revision in index.revisions.iter() {
+            if reseal_blob(session, revision, &needs_reseal)? {
+                resealed_revisions.push(revision.blob.as_str());
+            }
+        }
+
+        if !resealed.is_empty() || !resealed_revisions.is_empty() {
+            update_manifest(session, &index, &resealed, &resealed_revisions)?;
+        }
+        Ok(resealed.len() + resealed_revisions.len())
+    }
+
+    fn reseal_blob<F>(session: &Session, entry: &Entry, needs_reseal: &F) -> Result<bool, Error>
+    where
+        F: Fn(&[u8]) -> Result<bool, Error>,
+    {
+        let log_path = blob_path(session, &entry.blob);
+        let header = read_header(session, &log_path)?;
+        if !needs_reseal(&header)? {
+            return Ok(false);
+        }
+
+        let identity = session.identity(&entry.date, &entry.id);
+        if stream::is_stream(&header) {
+            // Opened straight into a new stream, so a large log is never in
+            // memory whole.
+            session
+                .db()
+                .storage()
+                .write_with(&log_path, &mut |writer| {
+                    let mut sealing = StreamWriter::new(session.master_key(), &identity, writer)?;
+                    read_log_into(session, &identity, &log_path, &mut sealing)?;
+                    sealing.finish()
+                })?;
+        } else {
+            let plaintext = decrypt_log(session, &identity, &log_path)?;
+            write_log(session, &identity, &plaintext, &log_path)?;
+        }
+        Ok(true)
+    }
+
+    // Compares the manifest with the logs on disk and describes every
+    // difference. A journal that never had a manifest gets one for its
+    // current logs.
+    pub fn verify_logs(session: &Session) -> Result<Vec<String>, Error> {
+        let index = log_index::load(session)?;
+        let manifest = match log_manifest::load(session, index.has_manifest) {
+            Ok(Some(manifest)) => manifest,
+            Ok(None) => {
+                update_manifest(session, &index, &[], &[])?;
+                return Ok(Vec::new());
+            }
+            Err(why) if why.kind() == ErrorKind::InvalidData => return Ok(vec![why.to_string()]),
+            Err(why) => return Err(why),
+        };
+
+        let mut problems = Vec::new();
+        for record in manifest.records.iter() {
+            let blob_path = match index.find(&record.date, &record.id) {
+                Some(entry) => blob_path(session, &entry.blob),
+                None => {
+                    problems.push(format!(
+                        "Log {} of {} was removed from the index",
+                        record.id, record.date
+                    ));
+                    continue;
+                }
+            };
+            match session.db().storage().read(&blob_path) {
+                Ok(content) if log_manifest::digest(&content)? == record.hash => (),
+                Ok(_) => problems.push(format!(
+                    "Log {} of {} was replaced or rolled back",
+                    record.id, record.date
+                )),
+                Err(_) => {
+                    problems.push(format!("Log {} of {} was deleted", record.id, record.date))
+                }
+            }
+        }
+        for entry in index.entries.iter() {
+            if manifest.find(&entry.date, &entry.id).is_none() {
+                problems.push(format!(
+                    "Log {} of {} was added outside the journal",
+                    entry.id, entry.date
+                ));
+            }
+        }
+        for recorded in manifest.revisions.iter() {
+            if !index
+                .revisions
+                .iter()
+                .any(|revision| revision.blob == recorded.blob)
+            {
+                problems.push(format!(
+                    "An earlier version of log {} of {} was removed from the index",
+                    recorded.id, recorded.date
+                ));
+                continue;
+            }
+            let blob_path = blob_path(session, &recorded.blob);
+            match session.db().storage().read(&blob_path) {
+                Ok(content) if log_manifest::digest(&content)? == recorded.hash => (),
+                Ok(_) => problems.push(format!(
+                    "An earlier version of log {} of {} was replaced",
+                    recorded.id, recorded.date
+                )),
+                Err(_) => problems.push(format!(
+                    "An earlier version of log {} of {} was deleted",
+                    recorded.id, recorded.date
+                )),
+            }
+        }
+
+        Ok(problems)
+    }
+
+    // Vouches for the logs as they are now, after a warning from
+    // `verify_logs` was looked into.
+    pub fn accept_logs(session: &Session) -> Result<(), Error> {
+        let mut index = log_index::load(session)?;
+        let count = index.entries.len() + index.revisions.len();
+        let blob_exists = |entry: &Entry| {
+            session
+                .db()
+                .storage()
+                .exists(&blob_path(session, &entry.blob))
+        };
+        index.entries.retain(blob_exists);
+        index.revisions.retain(blob_exists);
+        if index.entries.len() + index.revisions.len() != count {
+            log_index::save(session, &index)?;
+        }
+
+        let mut manifest = build_manifest(session, &index)?;
+        save_manifest(session, &index, &mut manifest)
+    }
+
+    // Records the changed logs and any earlier version the manifest does not
+    // know yet, and forgets the versions the index no longer keeps. Versions
+    // from before revisions were part of the manifest are taken as they are
+    // the first time it is updated.
+    fn update_manifest(
+        session: &Session,
+        index: &Index,
+        changed: &[(&str, &str)],
+        changed_revisions: &[&str],
+    ) -> Result<(), Error> {
+        let mut manifest = match log_manifest::load(session, index.has_manifest)? {
+            Some(manifest) => manifest,
+            None => build_manifest(session, index)?,
+        };
+        for (date, id) in changed.iter() {
+            let content = session
+                .db()
+                .storage()
+                .read(&get_blob_path(session, index, date, id)?)?;
+            manifest.record(date, id, &content)?;
+        }
+        manifest.revisions.retain(|recorded| {
+            index
+                .revisions
+                .iter()
+                .any(|revision| revision.blob == recorded.blob)
+        });
+        for revision in index.revisions.iter() {
+            if manifest.find_revision(&revision.blob).is_none()
+                || changed_revisions.contains(&revision.blob.as_str())
+            {
+                record_revision(session, &mut manifest, revision)?;
+            }
+        }
+
+        save_manifest(session, index, &mut manifest)
+    }
+
+    // Saves the manifest and notes in the index that the journal has one.
+    fn save_manifest(
+        session: &Session,
+        index: &Index,
+        manifest: &mut Manifest,
+    ) -> Result<(), Error> {
+        log_manifest::save(session, manifest)?;
+        if !index.has_manifest {
+            let mut index = index.clone();
+            index.has_manifest = true;
+            log_index::save(session, &index)?;
+        }
+
+        Ok(())
+    }
+
+    fn build_manifest(session: &Session, index: &Index) -> Result<Manifest, Error> {
+        let mut manifest = Manifest::default();
+        for entry in index.entries.iter() {
+            let content = session
+                .db()
+                .storage()
+                .read(&blob_path(session, &entry.blob))?;
+            manifest.record(&entry.date, &entry.id, &content)?;
+        }
+        for revision in index.revisions.iter() {
+            record_revision(session, &mut manifest, revision)?;
+        }
+
+        Ok(manifest)
+    }
+
+    fn record_revision(
+        session: &Session,
+        manifest: &mut Manifest,
+        revision: &Entry,
+    ) -> Result<(), Error> {
+        let content = session
+            .db()
+            .storage()
+            .read(&blob_path(session, &revision.blob))?;
+        manifest.record_revision(&revision.date, &revision.id, &revision.blob, &content)
+    }
+
+    // Moves logs from the <year>/<month>/<day>/log_N.dat tree into blobs. The
+    // identity each log is sealed with stays the same, so the files are only
+    // renamed. The index is saved before any file moves; running this again
+    // after an interruption picks up the blob names already recorded. Runs
+    // once after unlock, before the logs are browsed or rekeyed.
+    pub fn move_date_tree(session: &Session) -> Result<(), Error> {
+        let user = session.user();
+        if session.is_decoy() {
+            return Ok(());
+        }
+        let locations = get_date_tree_locations(session)?;
+        if locations.is_empty() {
+            return Ok(());
+        }
+
+        // A log from the tree keeps its position as its id, which it is sealed
+        // with.
+        let mut index = log_index::load(session)?;
+        for (date, pos) in locations.iter() {
+            let id = pos.to_string();
+            if index.find(date, &id).is_none() {
+                index.entries.push(Entry {
+                    date: date.clone(),
+                    id,
+                    blob: log_index::generate_blob_name()?,
+                    signature: None,
+                    sealed: false,
+                    saved: None,
+                });
+            }
+        }
+        log_index::save(session, &index)?;
+
+        let db = session.db();
+        let storage = db.storage();
+        for (date, pos) in locations.iter() {
+            let old_path =
+                file_system::generate_log_path(&get_user_logs_dir(db.ids_dir(), date, user)?, *pos);
+            let blob_path = get_blob_path(session, &index, date, &pos.to_string())?;
+            storage.rename(&old_path, &blob_path)?;
+        }
+
+        let root = file_system::generate_user_logs_root(db.ids_dir(), user);
+        for year in get_date_tree_dirs(session, &root)? {
+            storage.prune(&file_system::generate_path(&[&root, "/", &year]))?;
+        }
+
+        Ok(())
+    }
+
+    fn get_date_tree_locations(session: &Session) -> Result<Vec<(String, usize)>, Error> {
+        let storage = session.db().storage();
+        let root = file_system::generate_user_logs_root(session.db().ids_dir(), session.user());
+        let mut locations = Vec::new();
+        if !storage.exists(&root) {
+            return Ok(locations);
+        }
+
+        for year in get_date_tree_dirs(session, &root)? {
+            let year_dir = file_system::generate_path(&[&root, "/", &year]);
+            for month in get_date_tree_dirs(session, &year_dir)? {
+                let month_dir = file_system::generate_path(&[&year_dir, "/", &month]);
+                for day in get_date_tree_dirs(session, &month_dir)? {
+                    let day_dir = file_system::generate_path(&[&month_dir, "/", &day]);
+                    for log in storage.list(&day_dir)? {
+                        if let Some(pos) = file_system::parse_log_pos(&log) {
+                            locations.push((format!("{}_{}_{}", day, month, year), pos));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(locations)
+    }
+
+    fn get_date_tree_dirs(session: &Session, dir: &str) -> Result<Vec<String>, Error> {
+        Ok(session
+            .db()
+            .storage()
+            .list(dir)?
+            .into_iter()
+            .filter(|name| name.parse::<u32>().is_ok())
+            .collect())
+    }
+
+    fn read_header(session: &Session, file_path: &str) -> Result<Vec<u8>, Error> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        session
+            .db()
+            .storage()
+            .reader(file_path)?
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+
+        Ok(header)
+    }
+
+    pub fn decrypt_log(
+        session: &Session,
+        identity: &LogIdentity,
+        file_path: &str,
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        if !stream::is_stream(&read_header(session, file_path)?) {
+            return open_single(session, identity, file_path)
+                .map_err(|_| modified_log_error(identity));
+        }
+
+        // Streams are not compressed, so their plaintext is never longer than
+        // the file and reserving that much keeps the buffer from being copied
+        // as it grows.
+        let size = session.db().storage().size(file_path)? as usize;
+        let mut content = Zeroizing::new(Vec::with_capacity(size));
+        read_log_into(session, identity, file_path, &mut *content)?;
+
+        Ok(content)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::database::storage::storage::MemoryStorage;
+        use std::env;
+        use std::path::Path;
+        use std::sync::{Arc, Once};
+
+        const DATE: &str = "14_02_2026";
+
+        // Each test gets a database of its own in memory; only the key
+        // derivation settings are shared, and they are the same for all.
+        fn memory_db() -> Database {
+            static SETUP: Once = Once::new();
+            SETUP.call_once(|| {
+                env::set_var("KDF_TARGET_MS", "0");
+                env::set_var("KDF_MIN_MEMORY", "8");
+            });
+            Database::new(Arc::new(MemoryStorage::new()), "memory/logs", "memory/keys")
+        }
+
+        fn unlock_in(db: &Database, user: &str) -> Session {
+            key_chain::create_user(db, user, "password", None).unwrap();
+            key_chain::unlock(db, user, "password").unwrap()
+        }
+
+        fn unlock_in_memory(user: &str) -> Session {
+            unlock_in(&memory_db(), user)
+        }
+
+        #[test]
+        fn inserts_overwrites_and_reads_logs() {
+            let session = unlock_in_memory("insert");
+            let id = insert_log(DATE, &session, "first\n").unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"first\n"
+            );
+
+            overwrite_log(DATE, &session, "second\n", &id).unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"second\n"
+            );
+            assert_eq!(get_log_revisions(DATE, &session, &id).unwrap().len(), 1);
+            let revision = get_log_revision(DATE, &session, &id, 0, None).unwrap();
+            assert_eq!(revision.as_slice(), b"first\n");
+
+            restore_revision(DATE, &session, &id, 0, None).unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"first\n"
+            );
+            assert_eq!(get_log_revisions(DATE, &session, &id).unwrap().len(), 2);
+
+            let other = insert_log(DATE, &session, "third\n").unwrap();
+            let logs = get_day_logs(&session, "2026", "02", "14").unwrap();
+            let ids: Vec<&str> = logs.iter().map(|log| log.id.as_str()).collect();
+            assert_eq!(ids, vec![id.as_str(), other.as_str()]);
+            assert_eq!(get_years(&session).unwrap(), vec!["2026"]);
+            assert!(verify_logs(&session).unwrap().is_empty());
+            assert!(!Path::new("memory").exists());
+        }
+
+        #[test]
+        fn refuses_to_create_a_user_twice() {
+            let session = unlock_in_memory("taken");
+            let id = insert_log(DATE, &session, "mine\n").unwrap();
+            let slots = key_chain::get_slots(session.db(), "taken").unwrap().len();
+
+            let why = key_chain::create_user(session.db(), "taken", "other", None).unwrap_err();
+            assert_eq!(why.kind(), ErrorKind::AlreadyExists);
+            assert_eq!(
+                key_chain::get_slots(session.db(), "taken").unwrap().len(),
+                slots
+            );
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"mine\n"
+            );
+        }
+
+        #[test]
+        fn streams_large_logs() {
+            let session = unlock_in_memory("stream");
+            let large = "x".repeat(STREAM_THRESHOLD + 1);
+            let id = insert_log(DATE, &session, &large).unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                large.as_bytes()
+            );
+
+            overwrite_log(DATE, &session, "short\n", &id).unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"short\n"
+            );
+            let revision = get_log_revision(DATE, &session, &id, 0, None).unwrap();
+            assert_eq!(revision.as_slice(), large.as_bytes());
+        }
+
+        #[test]
+        fn seals_logs_with_their_own_password() {
+            let session = unlock_in_memory("sealed");
+            let id = insert_log(DATE, &session, "secret\n").unwrap();
+            seal_log(DATE, &session, &id, "log password").unwrap();
+            let denied = get_date_log(DATE, &session, &id).unwrap_err();
+            assert_eq!(denied.kind(), ErrorKind::PermissionDenied);
+            assert!(overwrite_log(DATE, &session, "changed\n", &id).is_err());
+
+            let (content, key) = open_sealed_log(DATE, &session, &id, "log password").unwrap();
+            assert_eq!(content.as_slice(), b"secret\n");
+            overwrite_sealed_log(DATE, &session, "changed\n", &id, &key).unwrap();
+            let content = get_sealed_log(DATE, &session, &id, "log password").unwrap();
+            assert_eq!(content.as_slice(), b"changed\n");
+
+            let (revision, key) =
+                open_sealed_revision(DATE, &session, &id, 0, "log password").unwrap();
+            assert_eq!(revision.as_slice(), b"secret\n");
+            overwrite_sealed_log(DATE, &session, "restored\n", &id, &key).unwrap();
+            restore_revision(DATE, &session, &id, 1, Some("log password")).unwrap();
+
+            unseal_log(DATE, &session, &id, "log password").unwrap();
+            assert_eq!(
+                get_date_log(DATE, &session, &id).unwrap().as_slice(),
+                b"changed\n"
+            );
+            let revision = get_log_revision(DATE, &session, &id, 0, None).unwrap();
+            assert_eq!(revision.as_slice(), b"secret\n");
+            let revision = get_log_revision(DATE, &session, &id, 2, None).unwrap();
+            assert_eq!(revision.as_slice(), b"restored\n");
+        }
+
+        #[test]
+        fn keeps_journals_apart() {
+            let db = memory_db();
+            let first = unlock_in(&db, "first");
+            let second = unlock_in(&db, "second");
+            let id = insert_log(DATE, &first, "mine\n").unwrap();
+
+            let missing = get_date_log(DATE, &second, &id).unwrap_err();
+            assert_eq!(missing.kind(), ErrorKind::NotFound);
+            assert!(!has_logs(&second));
+            assert_eq!(
+                get_date_log(DATE, &first, &id).unwrap().as_slice(),
+                b"mine\n"
+            );
+        }
     }
 }
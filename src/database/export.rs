@@ -0,0 +1,130 @@
+// An exported log is a plain text file that carries its author, where it sat
+// in the journal and a signature by the journal's signing key:
+//
+//   crypto_editor signed log
+//   user <user>
+//   date <dd_mm_yyyy>
+//   log <id>
+//   key <public key>
+//   signature <signature>
+//
+//   <content>
+//
+// Verifying it needs the journal's public key only, not its password. That
+// key is not protected on disk, so both exporting and verifying show its
+// fingerprint for the reader to compare out of band.
+pub mod log_export {
+    use crate::database::database_handler::logs_api;
+    use crate::database::keychain::key_chain;
+    use crate::database::storage::storage::Database;
+    use crate::encryption::container::container::LogIdentity;
+    use crate::encryption::session::session::Session;
+    use crate::encryption::signing::signing;
+    use crate::utils::utils::file_system;
+    use std::fs;
+    use std::io::{Error, ErrorKind};
+    use zeroize::Zeroizing;
+
+    const MAGIC: &str = "crypto_editor signed log";
+
+    #[derive(Debug)]
+    pub struct Export {
+        pub user: String,
+        pub date: String,
+        pub id: String,
+        pub fingerprint: String,
+        pub content: Vec<u8>,
+    }
+
+    // Returns the fingerprint of the key the log was signed with.
+    pub fn export_log(
+        date: &str,
+        session: &Session,
+        id: &str,
+        path: &str,
+    ) -> Result<String, Error> {
+        let content = logs_api::get_date_log(date, session, id)?;
+        let signing_key = key_chain::get_signing_key(session)?;
+        let signature = signing::sign(&signing_key, &session.identity(date, id), &content);
+
+        let mut exported = Zeroizing::new(
+            format!(
+                "{}\nuser {}\ndate {}\nlog {}\nkey {}\nsignature {}\n\n",
+                MAGIC,
+                session.user(),
+                date,
+                id,
+                file_system::to_hex(signing_key.public_key()),
+                file_system::to_hex(&signature)
+            )
+            .into_bytes(),
+        );
+        exported.extend_from_slice(&content);
+
+        file_system::write_file_atomic(path, &exported)?;
+        signing::fingerprint(signing_key.public_key()).map_err(Error::other)
+    }
+
+    // Checks the file against the public keys of the journal it claims to
+    // come from, so a file signed with some other key is rejected even if its
+    // own signature holds.
+    pub fn verify_export(db: &Database, path: &str) -> Result<Export, Error> {
+        let exported = fs::read(path)?;
+        let invalid = || Error::new(ErrorKind::InvalidData, "Not an exported log");
+        let split = exported
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .ok_or_else(invalid)?;
+        let header = String::from_utf8(exported[..split].to_vec()).map_err(|_| invalid())?;
+        let content = exported[split + 2..].to_vec();
+
+        let mut lines = header.lines();
+        if lines.next() != Some(MAGIC) {
+            return Err(invalid());
+        }
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.strip_prefix(' '))
+                .map(String::from)
+                .ok_or_else(invalid)
+        };
+        let user = field("user")?;
+        let date = field("date")?;
+        let id = field("log")?;
+        let public_key = file_system::from_hex(&field("key")?).ok_or_else(invalid)?;
+        let signature = file_system::from_hex(&field("signature")?).ok_or_else(invalid)?;
+
+        let journal_keys = key_chain::get_public_keys(db, &user).map_err(|_| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("No signing key is known for {}", user),
+            )
+        })?;
+        let identity = LogIdentity {
+            user: &user,
+            date: &date,
+            log_id: &id,
+        };
+        if !journal_keys.contains(&public_key)
+            || signing::verify(&public_key, &identity, &content, &signature).is_err()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Integrity check failed: the log was not signed by {} or was changed",
+                    user
+                ),
+            ));
+        }
+
+        Ok(Export {
+            user,
+            date,
+            id,
+            fingerprint: signing::fingerprint(&public_key).map_err(Error::other)?,
+            content,
+        })
+    }
+}
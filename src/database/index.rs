@@ -0,0 +1,349 @@
+// Logs are stored as blobs with random names, so the disk shows neither the
+// days a user wrote on nor how often. The per-user index maps each log, known
+// by its date and id, to its blob. It is sealed with the master key like a log,
+// one `date id blob [signature [sealed]] [@saved]` line per log, where `saved`
+// is the unix time the log was last saved. The logs of a day are listed in the
+// order they were written, which is the order of their lines. New logs get a
+// ULID as their id; logs written before that keep their position within the
+// day, which is bound into how they are sealed and signed. Every save
+// leaves the blob it replaces in place as an earlier version of the log, kept in
+// a line of the same form prefixed with `revision`, oldest first.
+pub mod log_index {
+    use crate::encryption::container::container::LogIdentity;
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::session::session::Session;
+    use crate::utils::utils::file_system;
+    use orion::util::secure_rand_bytes;
+    use std::io::{Error, ErrorKind};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Entry {
+        pub date: String,
+        pub id: String,
+        pub blob: String,
+        // Logs saved before signing was added have no signature.
+        pub signature: Option<Vec<u8>>,
+        // Sealed logs are locked with a password of their own as well.
+        pub sealed: bool,
+        // Logs saved before revisions were kept have no save time.
+        pub saved: Option<u64>,
+    }
+
+    impl Entry {
+        fn to_line(&self) -> String {
+            let mut line = format!("{} {} {}", self.date, self.id, self.blob);
+            if let Some(signature) = &self.signature {
+                line.push(' ');
+                line.push_str(&file_system::to_hex(signature));
+                if self.sealed {
+                    line.push_str(" sealed");
+                }
+            }
+            if let Some(saved) = self.saved {
+                line.push_str(&format!(" @{}", saved));
+            }
+            line.push('\n');
+            line
+        }
+
+        fn parse(line: &str) -> Result<Self, Error> {
+            let corrupt = || Error::new(ErrorKind::InvalidData, "Corrupt log index");
+            let mut fields: Vec<&str> = line.split(' ').collect();
+            let saved = match fields
+                .last()
+                .copied()
+                .and_then(|last| last.strip_prefix('@'))
+            {
+                Some(saved) => {
+                    fields.pop();
+                    Some(saved.parse::<u64>().map_err(|_| corrupt())?)
+                }
+                None => None,
+            };
+            let (date, id, blob, signature, sealed) = match fields.as_slice() {
+                [date, id, blob] => (date, id, blob, None, false),
+                [date, id, blob, signature] => (date, id, blob, Some(signature), false),
+                [date, id, blob, signature, "sealed"] => (date, id, blob, Some(signature), true),
+                _ => return Err(corrupt()),
+            };
+            let signature = match signature {
+                Some(signature) => Some(file_system::from_hex(signature).ok_or_else(corrupt)?),
+                None => None,
+            };
+
+            Ok(Self {
+                date: date.to_string(),
+                id: id.to_string(),
+                blob: blob.to_string(),
+                signature,
+                sealed,
+                saved,
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    pub struct Index {
+        pub entries: Vec<Entry>,
+        // Earlier versions of the logs, in the order they were replaced.
+        pub revisions: Vec<Entry>,
+        // Whether a manifest vouches for these logs, see `log_manifest`.
+        pub has_manifest: bool,
+    }
+
+    impl Index {
+        pub fn find(&self, date: &str, id: &str) -> Option<&Entry> {
+            self.entries
+                .iter()
+                .find(|entry| entry.date == date && entry.id == id)
+        }
+
+        pub fn find_mut(&mut self, date: &str, id: &str) -> Option<&mut Entry> {
+            self.entries
+                .iter_mut()
+                .find(|entry| entry.date == date && entry.id == id)
+        }
+
+        // Logs from before ids were ULIDs come first, in the order of their
+        // positions.
+        pub fn day(&self, date: &str) -> Vec<&Entry> {
+            let mut entries: Vec<&Entry> = self
+                .entries
+                .iter()
+                .filter(|entry| entry.date == date)
+                .collect();
+            entries.sort_by_key(|entry| entry.id.parse::<usize>().map_or((1, 0), |pos| (0, pos)));
+            entries
+        }
+
+        pub fn revisions(&self, date: &str, id: &str) -> Vec<&Entry> {
+            self.revisions
+                .iter()
+                .filter(|revision| revision.date == date && revision.id == id)
+                .collect()
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            if self.has_manifest {
+                bytes.extend_from_slice(b"manifest\n");
+            }
+            for entry in self.entries.iter() {
+                bytes.extend_from_slice(entry.to_line().as_bytes());
+            }
+            for revision in self.revisions.iter() {
+                bytes.extend_from_slice(b"revision ");
+                bytes.extend_from_slice(revision.to_line().as_bytes());
+            }
+            bytes
+        }
+
+        pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+            let contents = String::from_utf8(bytes.to_vec())
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Corrupt log index"))?;
+            let mut index = Self::default();
+            for line in contents.lines() {
+                if line == "manifest" {
+                    index.has_manifest = true;
+                    continue;
+                }
+                match line.strip_prefix("revision ") {
+                    Some(revision) => index.revisions.push(Entry::parse(revision)?),
+                    None => index.entries.push(Entry::parse(line)?),
+                }
+            }
+
+            Ok(index)
+        }
+    }
+
+    pub fn load(session: &Session) -> Result<Index, Error> {
+        let db = session.db();
+        let index_path = session.journal_path(
+            "index",
+            file_system::generate_index_path(db.ids_dir(), session.user()),
+        )?;
+        let storage = db.storage();
+        if !storage.exists(&index_path) {
+            return Ok(Index::default());
+        }
+
+        let content = storage.read(&index_path)?;
+        let identity = LogIdentity::index(session.user());
+        let plaintext = crypto::decrypt_str(session, &identity, &content).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Integrity check failed: the log index of {} was modified",
+                    session.user()
+                ),
+            )
+        })?;
+
+        Index::parse(&plaintext)
+    }
+
+    pub fn save(session: &Session, index: &Index) -> Result<(), Error> {
+        let db = session.db();
+        let index_path = session.journal_path(
+            "index",
+            file_system::generate_index_path(db.ids_dir(), session.user()),
+        )?;
+        let identity = LogIdentity::index(session.user());
+        let ciphertext =
+            crypto::encrypt_with_master_key(db, session.master_key(), &identity, &index.to_bytes())
+                .map_err(Error::other)?;
+
+        // Every save of the index follows a change to the blobs, so their
+        // times are levelled here.
+        let storage = db.storage();
+        storage.write(&index_path, &ciphertext)?;
+        storage.level_times(&file_system::generate_blobs_dir(
+            db.ids_dir(),
+            session.user(),
+        ))
+    }
+
+    // A ULID: the time in milliseconds followed by 80 random bits, in
+    // Crockford's base 32.
+    pub fn generate_log_id() -> Result<String, Error> {
+        const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis())
+            .unwrap_or(0);
+        let mut random = [0u8; 10];
+        secure_rand_bytes(&mut random).map_err(Error::other)?;
+
+        let value = (millis & ((1 << 48) - 1)) << 80
+            | random
+                .iter()
+                .fold(0u128, |value, byte| value << 8 | u128::from(*byte));
+
+        Ok((0..26)
+            .rev()
+            .map(|digit| ALPHABET[(value >> (5 * digit)) as usize & 31] as char)
+            .collect())
+    }
+
+    pub fn generate_blob_name() -> Result<String, Error> {
+        let mut bytes = [0u8; 16];
+        secure_rand_bytes(&mut bytes).map_err(Error::other)?;
+
+        Ok(file_system::to_hex(&bytes))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::thread;
+        use std::time::Duration;
+
+        #[test]
+        fn log_ids_are_ulids() {
+            for _ in 0..1000 {
+                let id = generate_log_id().unwrap();
+                assert_eq!(id.len(), 26);
+                assert!(id
+                    .bytes()
+                    .all(|byte| b"0123456789ABCDEFGHJKMNPQRSTVWXYZ".contains(&byte)));
+                // 128 bits in 26 digits leave the top two bits clear.
+                assert!(id.as_bytes()[0] <= b'7');
+            }
+        }
+
+        fn now_millis() -> u128 {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        }
+
+        #[test]
+        fn log_ids_sort_by_time() {
+            let mut ids = Vec::new();
+            for _ in 0..5 {
+                let before = now_millis();
+                let id = generate_log_id().unwrap();
+                let time = id[..10].bytes().fold(0u128, |time, byte| {
+                    let digit = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ"
+                        .iter()
+                        .position(|other| *other == byte)
+                        .unwrap();
+                    time << 5 | digit as u128
+                });
+                assert!(before <= time && time <= now_millis());
+                ids.push(id);
+                thread::sleep(Duration::from_millis(2));
+            }
+
+            let mut sorted = ids.clone();
+            sorted.sort();
+            assert_eq!(sorted, ids);
+        }
+
+        #[test]
+        fn log_ids_are_unique() {
+            let mut ids: Vec<String> = (0..1000).map(|_| generate_log_id().unwrap()).collect();
+            ids.sort();
+            ids.dedup();
+            assert_eq!(ids.len(), 1000);
+        }
+
+        #[test]
+        fn days_list_positions_before_ulids_in_written_order() {
+            let entry = |id: &str| Entry {
+                date: String::from("01_01_2026"),
+                id: String::from(id),
+                blob: String::from("blob"),
+                signature: None,
+                sealed: false,
+                saved: None,
+            };
+            let index = Index {
+                entries: vec![
+                    entry("01J0000000000000000000000Z"),
+                    entry("1"),
+                    entry("01J0000000000000000000000A"),
+                    entry("0"),
+                ],
+                revisions: Vec::new(),
+                has_manifest: false,
+            };
+            let ids: Vec<&str> = index
+                .day("01_01_2026")
+                .iter()
+                .map(|entry| entry.id.as_str())
+                .collect();
+            assert_eq!(
+                ids,
+                vec![
+                    "0",
+                    "1",
+                    "01J0000000000000000000000Z",
+                    "01J0000000000000000000000A"
+                ]
+            );
+        }
+
+        #[test]
+        fn index_lines_round_trip() {
+            let index = Index {
+                entries: vec![Entry {
+                    date: String::from("01_01_2026"),
+                    id: generate_log_id().unwrap(),
+                    blob: generate_blob_name().unwrap(),
+                    signature: Some(vec![1, 2, 3]),
+                    sealed: true,
+                    saved: Some(42),
+                }],
+                revisions: Vec::new(),
+                has_manifest: true,
+            };
+            let parsed = Index::parse(&index.to_bytes()).unwrap();
+            assert_eq!(parsed.entries, index.entries);
+            assert!(parsed.has_manifest);
+        }
+    }
+}
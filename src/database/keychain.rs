@@ -1,117 +1,807 @@
 pub mod key_chain {
+    use crate::database::database_handler::logs_api;
+    use crate::database::profile::profile;
+    use crate::database::storage::storage::Database;
+    use crate::database::throttle::throttle::{Record, Status};
+    use crate::encryption::container::container::{KdfParams, LogIdentity};
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::kdf_policy::kdf_policy::KdfPolicy;
+    use crate::encryption::recovery::recovery;
+    use crate::encryption::session::session::Session;
+    use crate::encryption::shamir::shamir::{self, Share};
+    use crate::encryption::signing::signing::{self, SigningKey, PUBLIC_KEY_SIZE};
+    use crate::encryption::slots::slots::{Slot, SlotKind, SALT_SIZE};
     use crate::utils::utils::file_system;
     use orion::kdf;
     use orion::pwhash;
-    use std::env;
+    use orion::util::secure_rand_bytes;
+    use std::convert::TryInto;
     use std::fs;
-    use std::fs::File;
-    use std::io::prelude::*;
     use std::io::{Error, ErrorKind};
+    use zeroize::Zeroizing;
 
-    pub fn get_key_salt(id: &str) -> Result<[u8; 16], Error> {
+    pub fn get_key_salt(db: &Database, id: &str) -> Result<[u8; 16], Error> {
         let mut buffer: [u8; 16] = [0; 16];
-        let complete_path = file_system::generate_salt_path(id).unwrap();
+        let complete_path = file_system::generate_salt_path(db.keys_dir(), id);
 
-        let mut f = File::open(complete_path)?;
-        f.read(&mut buffer)?;
+        let salt = db.storage().read(&complete_path)?;
+        if salt.len() < buffer.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Salt is too short"));
+        }
+        buffer.copy_from_slice(&salt[..16]);
         Ok(buffer)
     }
 
-    pub fn get_pass_hash(id: &str) -> Result<String, Error> {
-        let complete_path = file_system::generate_pass_path(id).unwrap();
+    pub fn get_pass_hash(db: &Database, id: &str) -> Result<String, Error> {
+        let complete_path = file_system::generate_pass_path(db.keys_dir(), id);
+
+        let hash = db.storage().read(&complete_path)?;
+        String::from_utf8(hash)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid password hash"))
+    }
+
+    pub fn get_master_key(db: &Database, id: &str) -> Result<Vec<u8>, Error> {
+        let complete_path = file_system::generate_master_key_path(db.keys_dir(), id);
+
+        db.storage().read(&complete_path)
+    }
+
+    pub fn get_recovery_key(db: &Database, id: &str) -> Result<Vec<u8>, Error> {
+        let complete_path = file_system::generate_recovery_key_path(db.keys_dir(), id);
+
+        db.storage().read(&complete_path)
+    }
 
-        Ok(fs::read_to_string(complete_path)?)
+    pub fn has_master_key(db: &Database, id: &str) -> bool {
+        db.storage()
+            .exists(&file_system::generate_master_key_path(db.keys_dir(), id))
     }
 
-    pub fn valid_auth(id: &str, pass: &str) -> Result<(), orion::errors::UnknownCryptoError> {
-        if let Ok(hash) = get_pass_hash(id) {
-            let stored_hash = pwhash::PasswordHash::from_encoded(&hash)?;
-            let password = pwhash::Password::from_slice(pass.as_ref())?;
-            return Ok(pwhash::hash_password_verify(&stored_hash, &password)?);
+    pub fn get_slots(db: &Database, id: &str) -> Result<Vec<(usize, Slot)>, Error> {
+        let storage = db.storage();
+        let slots_dir = file_system::generate_slots_dir(db.keys_dir(), id, "");
+        if !storage.exists(&slots_dir) {
+            return Ok(Vec::new());
         }
 
-        Err(orion::errors::UnknownCryptoError)
+        let mut slots = Vec::new();
+        for filename in storage.list(&slots_dir)? {
+            if let Some(pos) = file_system::parse_slot_pos(&filename) {
+                let content = storage.read(&file_system::generate_slot_path(&slots_dir, pos))?;
+                let slot = Slot::from_bytes(&content).map_err(|_| {
+                    Error::new(ErrorKind::InvalidData, format!("Corrupt key slot {}", pos))
+                })?;
+                slots.push((pos, slot));
+            }
+        }
+        slots.sort_by_key(|(pos, _)| *pos);
+
+        Ok(slots)
     }
 
-    pub fn create_user(id: &str, password: &str) -> Result<(), Error> {
-        create_key_salt(id)?;
-        create_password(id, password)?;
+    pub fn has_slots(db: &Database, id: &str) -> bool {
+        match get_slots(db, id) {
+            Ok(slots) => !slots.is_empty(),
+            Err(_) => false,
+        }
+    }
 
-        Ok(())
+    pub fn has_recovery_key(db: &Database, id: &str) -> bool {
+        match get_slots(db, id) {
+            Ok(slots) if !slots.is_empty() => slots
+                .iter()
+                .any(|(_, slot)| slot.kind == SlotKind::Recovery),
+            _ => db
+                .storage()
+                .exists(&file_system::generate_recovery_key_path(db.keys_dir(), id)),
+        }
+    }
+
+    // The public keys in signing.pub: the journal's own and its decoy's, or a
+    // stand-in where there is no decoy, in random order.
+    pub fn get_public_keys(db: &Database, id: &str) -> Result<Vec<Vec<u8>>, Error> {
+        let complete_path = file_system::generate_public_key_path(db.keys_dir(), id);
+        let keys = db.storage().read(&complete_path)?;
+        if keys.is_empty() || keys.len() % PUBLIC_KEY_SIZE != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Integrity check failed: the public keys of {} are corrupt",
+                    id
+                ),
+            ));
+        }
+
+        Ok(keys.chunks(PUBLIC_KEY_SIZE).map(<[u8]>::to_vec).collect())
+    }
+
+    fn save_public_keys(db: &Database, id: &str, mut keys: Vec<Vec<u8>>) -> Result<(), Error> {
+        // Fisher-Yates, so the order does not tell which key is whose.
+        for last in (1..keys.len()).rev() {
+            let mut random = [0u8; 8];
+            secure_rand_bytes(&mut random).map_err(Error::other)?;
+            let pick = (u64::from_le_bytes(random) % (last as u64 + 1)) as usize;
+            keys.swap(last, pick);
+        }
+
+        db.storage().write(
+            &file_system::generate_public_key_path(db.keys_dir(), id),
+            &keys.concat(),
+        )
+    }
+
+    // The signing key is created the first time it is needed, so journals
+    // from before signing get one on their next save. Its public key has to
+    // be listed in signing.pub, which is kept in the clear. A journal lists
+    // itself there the first time; a decoy is listed when it is added.
+    pub fn get_signing_key(session: &Session) -> Result<SigningKey, Error> {
+        let db = session.db();
+        let id = session.user();
+        let key_path = session.journal_path(
+            "signing.key",
+            file_system::generate_signing_key_path(db.keys_dir(), id),
+        )?;
+        let storage = db.storage();
+        if !storage.exists(&key_path) {
+            let (sealed, _) = signing::generate_signing_key(db, session.master_key(), id)
+                .map_err(Error::other)?;
+            storage.write(&key_path, &sealed)?;
+        }
+
+        let signing_key =
+            signing::open_signing_key(session, &storage.read(&key_path)?).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Integrity check failed: the signing key of {} was modified",
+                        id
+                    ),
+                )
+            })?;
+        let own = signing_key.public_key().to_vec();
+        let public_path = file_system::generate_public_key_path(db.keys_dir(), id);
+        let keys = if storage.exists(&public_path) {
+            get_public_keys(db, id)?
+        } else {
+            Vec::new()
+        };
+        if keys.contains(&own) {
+            // Journals from before decoys were listed kept their own key alone.
+            if keys.len() == 1 {
+                let stand_in =
+                    signing::stand_in_public_key(session.master_key()).map_err(Error::other)?;
+                save_public_keys(db, id, vec![own, stand_in])?;
+            }
+        } else if keys.is_empty() && !session.is_decoy() {
+            let stand_in =
+                signing::stand_in_public_key(session.master_key()).map_err(Error::other)?;
+            save_public_keys(db, id, vec![own, stand_in])?;
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Integrity check failed: the public key of {} does not match its signing key",
+                    id
+                ),
+            ));
+        }
+
+        Ok(signing_key)
+    }
+
+    fn add_slot(db: &Database, id: &str, slot: &Slot) -> Result<usize, Error> {
+        let pos = match get_slots(db, id)?.last() {
+            Some((last, _)) => last + 1,
+            None => 0,
+        };
+        write_slot(db, id, pos, slot)?;
+
+        Ok(pos)
+    }
+
+    fn write_slot(db: &Database, id: &str, pos: usize, slot: &Slot) -> Result<(), Error> {
+        let slots_dir = file_system::generate_slots_dir(db.keys_dir(), id, "");
+        let slot_path = file_system::generate_slot_path(&slots_dir, pos);
+
+        db.storage().write(&slot_path, &slot.to_bytes())
+    }
+
+    // Opens the journal, running the key derivation once for every slot that
+    // is tried. Users from before key slots are migrated to slots first, which
+    // rewrites their keys and moves their logs to the master key. Refuses to
+    // try the password while the user is throttled, see `throttle`.
+    pub fn unlock(
+        db: &Database,
+        id: &str,
+        secret: &str,
+    ) -> Result<Session, orion::errors::UnknownCryptoError> {
+        let record = Record::login(db, id);
+        if record.status() != Status::Open {
+            return Err(orion::errors::UnknownCryptoError);
+        }
+
+        let unlocked = if has_slots(db, id) || migrate_legacy_keys(db, id, secret).is_ok() {
+            Session::unlock(db, id, secret)
+        } else {
+            Err(orion::errors::UnknownCryptoError)
+        };
+        match unlocked {
+            Ok(_) => {
+                let _ = record.reset();
+            }
+            Err(_) => {
+                let _ = record.record_failure();
+            }
+        }
+
+        unlocked
+    }
+
+    // Users created before key slots have a salt, a password hash, a wrapped
+    // master key and possibly a recovery key in their key directory. Users
+    // created before envelope encryption lack even the master key, which is
+    // created here with the default parameters their logs were sealed with.
+    // Logs still sealed with the password-derived key are moved to the master
+    // key while the password is at hand, then the key files become slots.
+    fn migrate_legacy_keys(db: &Database, id: &str, password: &str) -> Result<(), Error> {
+        let hash = get_pass_hash(db, id)?;
+        let stored_hash = pwhash::PasswordHash::from_encoded(&hash).map_err(Error::other)?;
+        let pass = pwhash::Password::from_slice(password.as_ref()).map_err(Error::other)?;
+        if pwhash::hash_password_verify(&stored_hash, &pass).is_err() {
+            return Err(Error::new(ErrorKind::PermissionDenied, "Invalid password"));
+        }
+
+        if !has_master_key(db, id) {
+            create_master_key(db, id, password, &KdfParams::default())?;
+        }
+        let session = Session::unlock_legacy(db, id, password).map_err(Error::other)?;
+        logs_api::move_date_tree(&session)?;
+        logs_api::rekey_logs(&session)?;
+
+        move_legacy_keys(db, id)
+    }
+
+    // The slots are written to a staging directory that is renamed into place,
+    // so an interrupted move leaves the legacy files in charge.
+    fn move_legacy_keys(db: &Database, id: &str) -> Result<(), Error> {
+        let storage = db.storage();
+        let slots_dir = file_system::generate_slots_dir(db.keys_dir(), id, "");
+        let staged_dir = file_system::generate_slots_dir(db.keys_dir(), id, ".tmp");
+        if storage.exists(&staged_dir) {
+            storage.delete(&staged_dir)?;
+        }
+
+        let mut profile = profile::load(db, id)?;
+        let kind = match profile.requires_keyfile {
+            true => SlotKind::PasswordKeyfile,
+            false => SlotKind::Password,
+        };
+        let mut slots = vec![Slot {
+            kind,
+            salt: get_key_salt(db, id)?,
+            sealed: get_master_key(db, id)?,
+        }];
+        if let Ok(sealed) = get_recovery_key(db, id) {
+            slots.push(Slot {
+                kind: SlotKind::Recovery,
+                salt: [0u8; 16],
+                sealed,
+            });
+        }
+        for (pos, slot) in slots.iter().enumerate() {
+            storage.write(
+                &file_system::generate_slot_path(&staged_dir, pos),
+                &slot.to_bytes(),
+            )?;
+        }
+        storage.rename(&staged_dir, &slots_dir)?;
+
+        for legacy_path in [
+            file_system::generate_salt_path(db.keys_dir(), id),
+            file_system::generate_pass_path(db.keys_dir(), id),
+            file_system::generate_master_key_path(db.keys_dir(), id),
+            file_system::generate_recovery_key_path(db.keys_dir(), id),
+        ]
+        .iter()
+        {
+            if storage.exists(legacy_path) {
+                storage.delete(legacy_path)?;
+            }
+        }
+        // The slot kinds tell whether a keyfile is needed from now on.
+        profile.requires_keyfile = false;
+        profile::save(db, id, &profile)
+    }
+
+    // Refuses ids that are taken, so that creating a user never adds a slot
+    // to someone else's journal or clears their throttle.
+    pub fn create_user(
+        db: &Database,
+        id: &str,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        if user_exists(db, id) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("User {} already exists", id),
+            ));
+        }
+        let params = KdfPolicy::from_env().calibrate().map_err(Error::other)?;
+
+        let master_key = crypto::generate_master_key();
+        let slot = Slot::seal_password(
+            id,
+            &credential_secret(password, keyfile)?,
+            slot_kind(keyfile),
+            &master_key,
+            &params,
+        )
+        .map_err(Error::other)?;
+        add_slot(db, id, &slot)?;
+
+        Record::login(db, id).reset()
+    }
+
+    pub fn requires_keyfile(db: &Database, id: &str) -> bool {
+        match get_slots(db, id) {
+            Ok(slots) if !slots.is_empty() => slots
+                .iter()
+                .any(|(_, slot)| slot.kind == SlotKind::PasswordKeyfile),
+            _ => match profile::load(db, id) {
+                Ok(profile) => profile.requires_keyfile,
+                Err(_) => false,
+            },
+        }
     }
 
-    pub fn create_password(id: &str, password: &str) -> Result<(), Error> {
-        let pass_path = file_system::generate_pass_path(id).unwrap();
-        file_system::generate_key_dir(id)?;
-        let mut pass_file = File::create(pass_path)?;
+    pub fn read_keyfile(path: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let keyfile = Zeroizing::new(fs::read(path)?);
+        if keyfile.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "Keyfile is empty"));
+        }
+
+        Ok(keyfile)
+    }
+
+    // The secret every other function in this module authenticates with: the
+    // password alone, or the password mixed with the keyfile for slots that
+    // require one.
+    pub fn credential_secret(
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<Zeroizing<String>, Error> {
+        match keyfile {
+            Some(keyfile) => crypto::mix_keyfile(password, keyfile).map_err(Error::other),
+            None => Ok(Zeroizing::new(String::from(password))),
+        }
+    }
+
+    fn slot_kind(keyfile: Option<&[u8]>) -> SlotKind {
+        match keyfile {
+            Some(_) => SlotKind::PasswordKeyfile,
+            None => SlotKind::Password,
+        }
+    }
+
+    pub fn create_master_key(
+        db: &Database,
+        id: &str,
+        password: &str,
+        params: &KdfParams,
+    ) -> Result<(), Error> {
+        let master_key_path = file_system::generate_master_key_path(db.keys_dir(), id);
+
+        let master_key = crypto::generate_master_key();
+        let wrapped =
+            crypto::wrap_master_key(db, id, password, &master_key, params).map_err(Error::other)?;
+        db.storage().write(&master_key_path, &wrapped)
+    }
+
+    // Adds a recovery slot and returns its phrase, which is not stored
+    // anywhere.
+    pub fn create_recovery_key(session: &Session) -> Result<Zeroizing<String>, Error> {
+        let recovery_key = recovery::generate_recovery_key();
+        let slot = Slot::seal_recovery(session.user(), &recovery_key, session.master_key())
+            .map_err(Error::other)?;
+        add_slot(session.db(), session.user(), &slot)?;
+
+        recovery::to_mnemonic(&recovery_key).map_err(Error::other)
+    }
+
+    // Adds a recovery slot whose key is only ever shown split into `count`
+    // shares, any `threshold` of which recover the account.
+    pub fn create_recovery_shares(
+        session: &Session,
+        threshold: u8,
+        count: u8,
+    ) -> Result<Vec<Zeroizing<String>>, Error> {
+        let recovery_key = recovery::generate_recovery_key();
+        let shares =
+            shamir::split(recovery_key.unprotected_as_bytes(), threshold, count).map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "Need at least 2 shares to recover and no more than the number of shares",
+                )
+            })?;
+        let phrases = shares
+            .iter()
+            .map(|share| recovery::encode_words(&share.to_bytes()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::other)?;
+
+        let slot = Slot::seal_recovery(session.user(), &recovery_key, session.master_key())
+            .map_err(Error::other)?;
+        add_slot(session.db(), session.user(), &slot)?;
+
+        Ok(phrases)
+    }
+
+    pub fn recovery_key_from_phrase(phrase: &str) -> Result<kdf::SecretKey, Error> {
+        recovery::from_mnemonic(phrase)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid recovery phrase"))
+    }
+
+    // Returns how many shares are needed to recover, as recorded in the share.
+    pub fn parse_recovery_share(phrase: &str) -> Result<u8, Error> {
+        recovery::decode_words(phrase)
+            .and_then(|bytes| Share::from_bytes(&bytes))
+            .map(|share| share.threshold)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid recovery share"))
+    }
+
+    pub fn recovery_key_from_shares(
+        phrases: &[Zeroizing<String>],
+    ) -> Result<kdf::SecretKey, Error> {
+        let mut shares = Vec::with_capacity(phrases.len());
+        for phrase in phrases {
+            let bytes = recovery::decode_words(phrase)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid recovery share"))?;
+            shares.push(Share::from_bytes(&bytes).map_err(Error::other)?);
+        }
+
+        let secret = shamir::combine(&shares).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "Shares are repeated or belong to different recovery keys",
+            )
+        })?;
+        kdf::SecretKey::from_slice(&secret).map_err(Error::other)
+    }
+
+    pub fn add_password_slot(
+        session: &Session,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<usize, Error> {
+        let params = KdfPolicy::from_env().calibrate().map_err(Error::other)?;
+        let slot = Slot::seal_password(
+            session.user(),
+            &credential_secret(password, keyfile)?,
+            slot_kind(keyfile),
+            session.master_key(),
+            &params,
+        )
+        .map_err(Error::other)?;
+
+        add_slot(session.db(), session.user(), &slot)
+    }
+
+    // Adds a password slot that opens a new, empty decoy journal instead of
+    // this one. The slot is an ordinary password slot with the parameters of
+    // the current one; what marks the decoy is a blob sealed with its master
+    // key, so neither can be told apart without the password. With `wipe` set,
+    // opening the decoy removes every other slot.
+    pub fn add_duress_slot(session: &Session, password: &str, wipe: bool) -> Result<usize, Error> {
+        let db = session.db();
+        let id = session.user();
+        // Checked slot by slot, since unlocking could set off a wipe.
+        let taken = get_slots(db, id)?.iter().any(|(_, slot)| {
+            slot.kind != SlotKind::Recovery && slot.open_with_secret(id, password).is_ok()
+        });
+        if taken {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                "That password already opens a key slot",
+            ));
+        }
+        let params = match session.slot().and_then(|pos| get_slot_params(db, id, pos)) {
+            Some(params) => params,
+            None => KdfPolicy::from_env().calibrate().map_err(Error::other)?,
+        };
+
+        let master_key = crypto::generate_master_key();
+        let marker: &[u8] = if wipe { b"wipe" } else { b"keep" };
+        let sealed =
+            crypto::encrypt_with_master_key(db, &master_key, &LogIdentity::journal(id), marker)
+                .map_err(Error::other)?;
+        let blob = crypto::derive_blob_name(&master_key, "journal").map_err(Error::other)?;
+        db.storage().write(
+            &file_system::generate_blob_path(db.ids_dir(), id, &blob),
+            &sealed,
+        )?;
+
+        // The decoy's public key takes the place of the stand-in, so its
+        // exports verify like this journal's.
+        let (sealed_key, decoy_key) =
+            signing::generate_signing_key(db, &master_key, id).map_err(Error::other)?;
+        let blob = crypto::derive_blob_name(&master_key, "signing.key").map_err(Error::other)?;
+        db.storage().write(
+            &file_system::generate_blob_path(db.ids_dir(), id, &blob),
+            &sealed_key,
+        )?;
+        get_signing_key(session)?;
+        let stand_in = signing::stand_in_public_key(session.master_key()).map_err(Error::other)?;
+        let mut keys: Vec<Vec<u8>> = get_public_keys(db, id)?
+            .into_iter()
+            .filter(|key| *key != stand_in)
+            .collect();
+        keys.push(decoy_key);
+        save_public_keys(db, id, keys)?;
 
-        if let Ok(password) = pwhash::Password::from_slice(password.as_ref()) {
-            if let Ok(hash) = pwhash::hash_password(&password, 3, 1 << 16) {
-                pass_file.write_all(hash.unprotected_as_encoded().as_ref())?;
+        let slot = Slot::seal_password(id, password, SlotKind::Password, &master_key, &params)
+            .map_err(Error::other)?;
+        let mut duress = get_duress_slots(session)?;
+        duress.push(slot.salt);
+        save_duress_slots(session, &duress)?;
+        add_slot(db, id, &slot)
+    }
+
+    // The duress slots a journal added, known by their salts, in a blob sealed
+    // with its master key so that only the journal can tell them from its own
+    // password slots.
+    fn get_duress_slots(session: &Session) -> Result<Vec<[u8; SALT_SIZE]>, Error> {
+        let id = session.user();
+        let path = duress_slots_path(session)?;
+        let storage = session.db().storage();
+        if !storage.exists(&path) {
+            return Ok(Vec::new());
+        }
+
+        let salts = crypto::decrypt_str(
+            session,
+            &LogIdentity::duress_slots(id),
+            &storage.read(&path)?,
+        )
+        .map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Integrity check failed: the key slots of {} were modified",
+                    id
+                ),
+            )
+        })?;
+        Ok(salts
+            .chunks_exact(SALT_SIZE)
+            .map(|salt| salt.try_into().unwrap())
+            .collect())
+    }
+
+    fn save_duress_slots(session: &Session, salts: &[[u8; SALT_SIZE]]) -> Result<(), Error> {
+        let sealed = crypto::encrypt_with_master_key(
+            session.db(),
+            session.master_key(),
+            &LogIdentity::duress_slots(session.user()),
+            &salts.concat(),
+        )
+        .map_err(Error::other)?;
+        session
+            .db()
+            .storage()
+            .write(&duress_slots_path(session)?, &sealed)
+    }
+
+    fn duress_slots_path(session: &Session) -> Result<String, Error> {
+        let blob =
+            crypto::derive_blob_name(session.master_key(), "duress.slots").map_err(Error::other)?;
+        Ok(file_system::generate_blob_path(
+            session.db().ids_dir(),
+            session.user(),
+            &blob,
+        ))
+    }
 
-                return Ok(());
+    // Returns whether the session opened a decoy journal, and if so whether
+    // that should wipe the other slots.
+    pub fn open_decoy(session: &Session) -> Option<bool> {
+        let db = session.db();
+        let id = session.user();
+        let blob = crypto::derive_blob_name(session.master_key(), "journal").ok()?;
+        let sealed = db
+            .storage()
+            .read(&file_system::generate_blob_path(db.ids_dir(), id, &blob))
+            .ok()?;
+        let marker = crypto::decrypt_str(session, &LogIdentity::journal(id), &sealed).ok()?;
+
+        Some(marker == b"wipe")
+    }
+
+    // The slots are overwritten before they are removed, see `Storage::wipe`.
+    pub fn wipe_other_slots(db: &Database, id: &str, keep: usize) -> Result<(), Error> {
+        let slots_dir = file_system::generate_slots_dir(db.keys_dir(), id, "");
+        for (pos, _) in get_slots(db, id)? {
+            if pos != keep {
+                db.storage()
+                    .wipe(&file_system::generate_slot_path(&slots_dir, pos))?;
             }
         }
 
-        Err(Error::new(ErrorKind::NotFound, "CryptoError"))
+        Ok(())
     }
 
-    pub fn create_key_salt(id: &str) -> Result<(), Error> {
-        let salt_path = file_system::generate_salt_path(id).unwrap();
-        file_system::generate_key_dir(id)?;
+    // At least one password slot has to stay, since the journal can only be
+    // opened for writing with a password. Duress slots open another journal,
+    // so they do not count.
+    pub fn remove_slot(session: &Session, pos: usize) -> Result<(), Error> {
+        let db = session.db();
+        let id = session.user();
+        let slots = get_slots(db, id)?;
+        let removed = match slots.iter().find(|(slot_pos, _)| *slot_pos == pos) {
+            Some((_, slot)) => slot,
+            None => return Err(Error::new(ErrorKind::NotFound, "No such key slot")),
+        };
+        let duress = get_duress_slots(session)?;
+        let is_own_password =
+            |slot: &Slot| slot.kind != SlotKind::Recovery && !duress.contains(&slot.salt);
+        let password_slots = slots
+            .iter()
+            .filter(|(_, slot)| is_own_password(slot))
+            .count();
+        if is_own_password(removed) && password_slots <= 1 {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "Cannot remove the last password slot",
+            ));
+        }
+
+        // Overwritten first, so the revoked secret opens no copy left on disk.
+        let slots_dir = file_system::generate_slots_dir(db.keys_dir(), id, "");
+        db.storage()
+            .wipe(&file_system::generate_slot_path(&slots_dir, pos))?;
+        if duress.contains(&removed.salt) {
+            let kept: Vec<[u8; SALT_SIZE]> = duress
+                .into_iter()
+                .filter(|salt| *salt != removed.salt)
+                .collect();
+            save_duress_slots(session, &kept)?;
+        }
 
-        let mut salt_file = File::create(salt_path)?;
-        let salt = kdf::Salt::default();
-        salt_file.write_all(salt.as_ref())?;
         Ok(())
     }
 
-    pub fn get_env_var(var_name: &str) -> Result<String, Error> {
-        match env::var(var_name) {
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                var_name.to_owned() + " env variable Not Found",
-            )),
-            Ok(key_dir) => Ok(key_dir),
+    // Adds a new password slot: the slots opened with the forgotten password
+    // stay until they are removed.
+    pub fn recover_account(
+        db: &Database,
+        id: &str,
+        recovery_key: &kdf::SecretKey,
+        new_password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        // Legacy users only have a recovery key once their logs were moved to
+        // the master key, so their key files can become slots right away.
+        if !has_slots(db, id) {
+            move_legacy_keys(db, id)?;
+        }
+        let session = Session::recover(db, id, recovery_key).map_err(|_| {
+            Error::new(
+                ErrorKind::PermissionDenied,
+                "The recovery key does not open any key slot",
+            )
+        })?;
+
+        add_password_slot(&session, new_password, keyfile)?;
+        Record::login(db, id).reset()
+    }
+
+    // Replaces the slot the old secret opens. This also adds, replaces or
+    // removes the keyfile: the new slot requires `keyfile` when it is given
+    // and only the password otherwise.
+    pub fn change_password(
+        db: &Database,
+        id: &str,
+        old_secret: &str,
+        new_password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let session = unlock_for_change(db, id, old_secret)?;
+        let pos = session.slot().unwrap();
+        let policy = KdfPolicy::from_env();
+        let params = match get_slot_params(db, id, pos) {
+            Some(params) if policy.is_satisfied_by(&params) => params,
+            _ => policy.calibrate().map_err(Error::other)?,
+        };
+
+        let slot = Slot::seal_password(
+            id,
+            &credential_secret(new_password, keyfile)?,
+            slot_kind(keyfile),
+            session.master_key(),
+            &params,
+        )
+        .map_err(Error::other)?;
+        write_slot(db, id, pos, &slot)
+    }
+
+    pub fn needs_kdf_upgrade(session: &Session) -> bool {
+        match session.slot() {
+            Some(pos) => match get_slot_params(session.db(), session.user(), pos) {
+                Some(params) => !KdfPolicy::from_env().is_satisfied_by(&params),
+                None => false,
+            },
+            None => false,
         }
     }
 
+    pub fn upgrade_kdf_params(session: &Session, secret: &str) -> Result<(), Error> {
+        let db = session.db();
+        let id = session.user();
+        let pos = match session.slot() {
+            Some(pos) => pos,
+            None => return Err(Error::new(ErrorKind::NotFound, "No key slot to upgrade")),
+        };
+        let kind = match get_slots(db, id)?
+            .into_iter()
+            .find(|(slot_pos, _)| *slot_pos == pos)
+        {
+            Some((_, slot)) => slot.kind,
+            None => return Err(Error::new(ErrorKind::NotFound, "No such key slot")),
+        };
+        let params = KdfPolicy::from_env().calibrate().map_err(Error::other)?;
+
+        let slot = Slot::seal_password(id, secret, kind, session.master_key(), &params)
+            .map_err(Error::other)?;
+        write_slot(db, id, pos, &slot)
+    }
+
+    fn get_slot_params(db: &Database, id: &str, pos: usize) -> Option<KdfParams> {
+        get_slots(db, id)
+            .ok()?
+            .into_iter()
+            .find(|(slot_pos, _)| *slot_pos == pos)
+            .and_then(|(_, slot)| slot.params())
+    }
+
+    fn unlock_for_change(db: &Database, id: &str, secret: &str) -> Result<Session, Error> {
+        unlock(db, id, secret)
+            .map_err(|_| Error::new(ErrorKind::PermissionDenied, "Invalid password"))
+    }
+
     #[allow(dead_code)]
-    fn find_id_filename(id: &str) -> Result<String, Error> {
-        match get_key_ids() {
+    fn find_id_filename(db: &Database, id: &str) -> Result<String, Error> {
+        match get_key_ids(db) {
             None => Err(Error::new(ErrorKind::NotFound, "Could not get key ids")),
             Some(ids) => {
                 let mut it = ids.iter();
                 match it.position(|key| key == id) {
-                    Some(found) => Ok(file_system::generate_path(&[&ids[found], &".txt"])),
+                    Some(found) => Ok(file_system::generate_path(&[&ids[found], ".txt"])),
                     None => Err(Error::new(ErrorKind::NotFound, "Could not find id")),
                 }
             }
         }
     }
 
-    pub fn id_exists(id: &str) -> bool {
-        if let Some(ids) = get_key_ids() {
+    // Whether anything is kept for `id`, keys or logs.
+    pub fn user_exists(db: &Database, id: &str) -> bool {
+        let storage = db.storage();
+        storage.exists(&file_system::generate_key_dir(db.keys_dir(), id))
+            || storage.exists(&file_system::generate_user_logs_root(db.ids_dir(), id))
+    }
+
+    pub fn id_exists(db: &Database, id: &str) -> bool {
+        if let Some(ids) = get_key_ids(db) {
             return ids.contains(&String::from(id));
         }
-        return false;
+        false
     }
 
-    pub fn get_key_names() -> Result<Vec<String>, Error> {
-        match env::var("DATABASE_KEYS_DIR") {
-            Ok(ids_dir) => {
-                let ids = file_system::read_dir_to_string(ids_dir)?;
-                Ok(ids)
-            }
-            Err(_) => Err(Error::new(
-                ErrorKind::NotFound,
-                "DATABASE_IDS_DIR env variable Not Found",
-            )),
-        }
+    pub fn get_key_names(db: &Database) -> Result<Vec<String>, Error> {
+        db.list_users()
     }
 
-    pub fn get_key_ids() -> Option<Vec<String>> {
-        match get_key_names() {
+    pub fn get_key_ids(db: &Database) -> Option<Vec<String>> {
+        match get_key_names(db) {
             Ok(key_filenames) => {
                 let keys = key_filenames
                     .into_iter()
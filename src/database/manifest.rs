@@ -0,0 +1,293 @@
+// The manifest records a hash of every log's ciphertext, so a blob that was
+// deleted, swapped for an older copy or added by hand stands out even though
+// each blob still opens on its own. It is sealed with the master key next to
+// the logs. Every save bumps a sequence number that is also sealed into a
+// checkpoint in the key directory, together with a hash of the manifest, so
+// restoring the logs directory from a backup is caught as well. The sealed
+// index notes that the journal has a manifest, so removing the manifest and
+// its checkpoint together does not pass for a journal that never had one.
+//
+// Earlier versions of a log are recorded by blob, since they share the date
+// and id of the log they belong to.
+pub mod log_manifest {
+    use crate::encryption::container::container::LogIdentity;
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::session::session::Session;
+    use crate::utils::utils::file_system;
+    use orion::hazardous::hash::blake2b::Blake2b;
+    use std::io::{Error, ErrorKind};
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Record {
+        pub date: String,
+        pub id: String,
+        pub hash: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Revision {
+        pub date: String,
+        pub id: String,
+        pub blob: String,
+        pub hash: String,
+    }
+
+    #[derive(Debug, Default)]
+    pub struct Manifest {
+        pub sequence: u64,
+        pub records: Vec<Record>,
+        pub revisions: Vec<Revision>,
+    }
+
+    impl Manifest {
+        pub fn find(&self, date: &str, id: &str) -> Option<&Record> {
+            self.records
+                .iter()
+                .find(|record| record.date == date && record.id == id)
+        }
+
+        pub fn record(&mut self, date: &str, id: &str, ciphertext: &[u8]) -> Result<(), Error> {
+            let hash = digest(ciphertext)?;
+            match self
+                .records
+                .iter_mut()
+                .find(|record| record.date == date && record.id == id)
+            {
+                Some(record) => record.hash = hash,
+                None => self.records.push(Record {
+                    date: date.to_string(),
+                    id: id.to_string(),
+                    hash,
+                }),
+            }
+
+            Ok(())
+        }
+
+        pub fn find_revision(&self, blob: &str) -> Option<&Revision> {
+            self.revisions.iter().find(|revision| revision.blob == blob)
+        }
+
+        pub fn record_revision(
+            &mut self,
+            date: &str,
+            id: &str,
+            blob: &str,
+            ciphertext: &[u8],
+        ) -> Result<(), Error> {
+            let hash = digest(ciphertext)?;
+            match self
+                .revisions
+                .iter_mut()
+                .find(|revision| revision.blob == blob)
+            {
+                Some(revision) => revision.hash = hash,
+                None => self.revisions.push(Revision {
+                    date: date.to_string(),
+                    id: id.to_string(),
+                    blob: blob.to_string(),
+                    hash,
+                }),
+            }
+
+            Ok(())
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = format!("{}\n", self.sequence).into_bytes();
+            for record in self.records.iter() {
+                bytes.extend_from_slice(
+                    format!("{} {} {}\n", record.date, record.id, record.hash).as_bytes(),
+                );
+            }
+            for revision in self.revisions.iter() {
+                bytes.extend_from_slice(
+                    format!(
+                        "revision {} {} {} {}\n",
+                        revision.date, revision.id, revision.blob, revision.hash
+                    )
+                    .as_bytes(),
+                );
+            }
+            bytes
+        }
+
+        pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+            let corrupt = || Error::new(ErrorKind::InvalidData, "Corrupt log manifest");
+            let contents = String::from_utf8(bytes.to_vec()).map_err(|_| corrupt())?;
+            let mut lines = contents.lines();
+            let sequence = lines
+                .next()
+                .and_then(|line| line.parse::<u64>().ok())
+                .ok_or_else(corrupt)?;
+
+            let mut records = Vec::new();
+            let mut revisions = Vec::new();
+            for line in lines {
+                let fields: Vec<&str> = line.split(' ').collect();
+                match fields.as_slice() {
+                    [date, id, hash] => records.push(Record {
+                        date: date.to_string(),
+                        id: id.to_string(),
+                        hash: hash.to_string(),
+                    }),
+                    ["revision", date, id, blob, hash] => revisions.push(Revision {
+                        date: date.to_string(),
+                        id: id.to_string(),
+                        blob: blob.to_string(),
+                        hash: hash.to_string(),
+                    }),
+                    _ => return Err(corrupt()),
+                }
+            }
+
+            Ok(Self {
+                sequence,
+                records,
+                revisions,
+            })
+        }
+    }
+
+    pub fn digest(bytes: &[u8]) -> Result<String, Error> {
+        let mut state = Blake2b::new(None, 32).map_err(Error::other)?;
+        state.update(bytes).map_err(Error::other)?;
+        let digest = state.finalize().map_err(Error::other)?;
+
+        Ok(file_system::to_hex(digest.as_ref()))
+    }
+
+    // Returns None for a journal that has never had a manifest. `expected`
+    // tells whether the index says it has one.
+    pub fn load(session: &Session, expected: bool) -> Result<Option<Manifest>, Error> {
+        let db = session.db();
+        let user = session.user();
+        let manifest_path = session.journal_path(
+            "manifest",
+            file_system::generate_manifest_path(db.ids_dir(), user),
+        )?;
+        let checkpoint_path = session.journal_path(
+            "manifest.head",
+            file_system::generate_checkpoint_path(db.keys_dir(), user),
+        )?;
+        let storage = db.storage();
+        let has_manifest = storage.exists(&manifest_path);
+        let has_checkpoint = storage.exists(&checkpoint_path);
+        if !has_manifest && !has_checkpoint && !expected {
+            return Ok(None);
+        }
+        if !has_manifest {
+            return Err(tampered(format!(
+                "the log manifest of {} was deleted",
+                user
+            )));
+        }
+
+        let plaintext = open(session, &LogIdentity::manifest(user), &manifest_path)
+            .map_err(|_| tampered(format!("the log manifest of {} was modified", user)))?;
+        let manifest = Manifest::parse(&plaintext)?;
+
+        let expected = format!("{} {}", manifest.sequence, digest(&plaintext)?);
+        let checkpoint = if has_checkpoint {
+            open(session, &LogIdentity::checkpoint(user), &checkpoint_path).ok()
+        } else {
+            None
+        };
+        if checkpoint.as_deref() != Some(expected.as_bytes()) {
+            return Err(tampered(format!(
+                "the logs of {} were rolled back to an earlier state",
+                user
+            )));
+        }
+
+        Ok(Some(manifest))
+    }
+
+    // The manifest and the checkpoint that vouches for it are committed
+    // together, manifest first.
+    pub fn save(session: &Session, manifest: &mut Manifest) -> Result<(), Error> {
+        let user = session.user();
+        manifest.sequence += 1;
+        let plaintext = manifest.to_bytes();
+        let checkpoint = format!("{} {}", manifest.sequence, digest(&plaintext)?);
+
+        let sealed_manifest = seal(session, &LogIdentity::manifest(user), &plaintext)?;
+        let sealed_checkpoint = seal(
+            session,
+            &LogIdentity::checkpoint(user),
+            checkpoint.as_bytes(),
+        )?;
+        let db = session.db();
+        db.storage().commit(&[
+            (
+                &session.journal_path(
+                    "manifest",
+                    file_system::generate_manifest_path(db.ids_dir(), user),
+                )?,
+                &sealed_manifest,
+            ),
+            (
+                &session.journal_path(
+                    "manifest.head",
+                    file_system::generate_checkpoint_path(db.keys_dir(), user),
+                )?,
+                &sealed_checkpoint,
+            ),
+        ])
+    }
+
+    fn open(session: &Session, identity: &LogIdentity, path: &str) -> Result<Vec<u8>, Error> {
+        let content = session.db().storage().read(path)?;
+        crypto::decrypt_str(session, identity, &content).map_err(Error::other)
+    }
+
+    fn seal(session: &Session, identity: &LogIdentity, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        crypto::encrypt_with_master_key(session.db(), session.master_key(), identity, plaintext)
+            .map_err(Error::other)
+    }
+
+    fn tampered(reason: String) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Integrity check failed: {}", reason),
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn manifest_round_trips_with_revisions() {
+            let mut manifest = Manifest {
+                sequence: 7,
+                ..Manifest::default()
+            };
+            manifest.record("01_01_2026", "0", b"current").unwrap();
+            manifest
+                .record_revision("01_01_2026", "0", "ab01", b"earlier")
+                .unwrap();
+            manifest
+                .record_revision("01_01_2026", "0", "ab01", b"resealed")
+                .unwrap();
+
+            let parsed = Manifest::parse(&manifest.to_bytes()).unwrap();
+            assert_eq!(parsed.sequence, 7);
+            assert_eq!(parsed.records, manifest.records);
+            assert_eq!(parsed.revisions, manifest.revisions);
+            assert_eq!(parsed.revisions.len(), 1);
+            assert_eq!(
+                parsed.find_revision("ab01").unwrap().hash,
+                digest(b"resealed").unwrap()
+            );
+        }
+
+        #[test]
+        fn manifests_without_revisions_still_parse() {
+            let parsed = Manifest::parse(b"3\n01_01_2026 0 00ff\n").unwrap();
+            assert_eq!(parsed.records.len(), 1);
+            assert!(parsed.revisions.is_empty());
+            assert!(Manifest::parse(b"3\nrevision 01_01_2026 0 ab01\n").is_err());
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// Per-user settings kept next to the credentials, one `key=value` per line.
+// Users whose keys were not moved into slots yet may have a profile telling
+// whether their password has to be mixed with a keyfile. Any user can keep
+// their compression setting in it, see `compression`.
+pub mod profile {
+    use crate::database::storage::storage::Database;
+    use crate::encryption::compression::compression::Compression;
+    use crate::utils::utils::file_system;
+    use std::io::{Error, ErrorKind};
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Profile {
+        pub requires_keyfile: bool,
+        pub compression: Option<Compression>,
+    }
+
+    impl Profile {
+        pub fn parse(contents: &str) -> Result<Self, Error> {
+            let mut profile = Profile::default();
+            for line in contents.lines().map(str::trim) {
+                if line.is_empty() {
+                    continue;
+                }
+                match line.split_once('=') {
+                    Some(("keyfile", value)) => profile.requires_keyfile = value == "required",
+                    Some(("compression", value)) => profile.compression = Compression::parse(value),
+                    Some(_) => (),
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Invalid profile line: {}", line),
+                        ))
+                    }
+                }
+            }
+
+            Ok(profile)
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut contents = String::new();
+            if self.requires_keyfile {
+                contents.push_str("keyfile=required\n");
+            }
+            if let Some(compression) = self.compression {
+                contents.push_str(&format!("compression={}\n", compression.name()));
+            }
+            contents.into_bytes()
+        }
+    }
+
+    pub fn load(db: &Database, id: &str) -> Result<Profile, Error> {
+        let profile_path = file_system::generate_profile_path(db.keys_dir(), id);
+        match db.storage().read(&profile_path) {
+            Ok(contents) => Profile::parse(&String::from_utf8_lossy(&contents)),
+            Err(why) if why.kind() == ErrorKind::NotFound => Ok(Profile::default()),
+            Err(why) => Err(why),
+        }
+    }
+
+    // Writes the profile, or removes it once it holds nothing but defaults.
+    pub fn save(db: &Database, id: &str, profile: &Profile) -> Result<(), Error> {
+        let profile_path = file_system::generate_profile_path(db.keys_dir(), id);
+        let storage = db.storage();
+        if *profile != Profile::default() {
+            storage.write(&profile_path, &profile.to_bytes())
+        } else if storage.exists(&profile_path) {
+            storage.delete(&profile_path)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn set_compression(db: &Database, id: &str, compression: Compression) -> Result<(), Error> {
+        let mut profile = load(db, id)?;
+        profile.compression = Some(compression);
+        save(db, id, &profile)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn round_trips_settings() {
+            let profile = Profile {
+                requires_keyfile: true,
+                compression: Some(Compression::None),
+            };
+            let contents = String::from_utf8(profile.to_bytes()).unwrap();
+            assert_eq!(Profile::parse(&contents).unwrap(), profile);
+            assert_eq!(Profile::parse("").unwrap(), Profile::default());
+        }
+
+        #[test]
+        fn reads_older_profiles() {
+            let profile = Profile::parse("keyfile=required\nunknown=1\n").unwrap();
+            assert!(profile.requires_keyfile);
+            assert_eq!(profile.compression, None);
+            assert!(Profile::parse("keyfile").is_err());
+        }
+    }
+}
@@ -0,0 +1,608 @@
+// Everything the journal keeps, logs and keys alike, is read and written
+// through a `Storage`. Paths are the ones `file_system` builds from the
+// directories of a `Database`; a backend decides what they map to. `FsStorage`
+// keeps them as files, `MemoryStorage` in a map that lives as long as it does
+// and `vault::VaultStorage` in a single encrypted file. A `Database` is handed
+// to everything that reads or writes, so several can be open side by side.
+pub mod storage {
+    use crate::utils::utils::file_system;
+    use orion::util::secure_rand_bytes;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::io::prelude::*;
+    use std::io::{BufWriter, Cursor, Error, ErrorKind};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use zeroize::Zeroize;
+
+    pub trait Storage: Send + Sync {
+        fn read(&self, path: &str) -> Result<Vec<u8>, Error>;
+
+        fn reader(&self, path: &str) -> Result<Box<dyn Read>, Error>;
+
+        fn size(&self, path: &str) -> Result<u64, Error>;
+
+        // True for files and for directories that hold anything.
+        fn exists(&self, path: &str) -> bool;
+
+        // Names of the files and directories right under `dir`, hidden ones
+        // included.
+        fn list(&self, dir: &str) -> Result<Vec<String>, Error>;
+
+        // Replaces the file at once, creating its directory as needed.
+        fn write(&self, path: &str, contents: &[u8]) -> Result<(), Error>;
+
+        // Like `write`, for contents that are written piece by piece. Nothing
+        // is replaced when `write` fails.
+        fn write_with(
+            &self,
+            path: &str,
+            write: &mut dyn FnMut(&mut dyn Write) -> Result<(), Error>,
+        ) -> Result<(), Error>;
+
+        // Writes several files together: none is replaced unless all of them
+        // could be written, and they are replaced in order, so a reader that
+        // sees a later file also sees the earlier ones. Only the vault
+        // replaces them all at once; elsewhere a crash may leave just the
+        // first ones replaced, so the last file should vouch for the others.
+        fn commit(&self, writes: &[(&str, &[u8])]) -> Result<(), Error>;
+
+        // Removes a file, or a directory with everything in it.
+        fn delete(&self, path: &str) -> Result<(), Error>;
+
+        // Removes a file so that its contents cannot be read back from the
+        // medium either, as far as the backend can tell. Backends whose
+        // `delete` already leaves nothing behind can keep the default.
+        fn wipe(&self, path: &str) -> Result<(), Error> {
+            self.delete(path)
+        }
+
+        // Gives every file right under `dir` the same modification time, so
+        // the times do not tell which file was written when. Backends that
+        // keep no times can keep the default.
+        fn level_times(&self, _dir: &str) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn rename(&self, from: &str, to: &str) -> Result<(), Error>;
+
+        // Removes `dir` and the directories below it that hold no files.
+        fn prune(&self, dir: &str) -> Result<(), Error>;
+    }
+
+    // A backend with the directory the logs of every user are kept under and
+    // the one their keys are kept under.
+    #[derive(Clone)]
+    pub struct Database {
+        storage: Arc<dyn Storage>,
+        ids_dir: String,
+        keys_dir: String,
+    }
+
+    impl Database {
+        pub fn new(storage: Arc<dyn Storage>, ids_dir: &str, keys_dir: &str) -> Self {
+            Self {
+                storage,
+                ids_dir: String::from(ids_dir),
+                keys_dir: String::from(keys_dir),
+            }
+        }
+
+        pub fn storage(&self) -> &Arc<dyn Storage> {
+            &self.storage
+        }
+
+        pub fn ids_dir(&self) -> &str {
+            &self.ids_dir
+        }
+
+        pub fn keys_dir(&self) -> &str {
+            &self.keys_dir
+        }
+
+        // Every user with a key directory.
+        pub fn list_users(&self) -> Result<Vec<String>, Error> {
+            Ok(self
+                .storage
+                .list(&self.keys_dir)?
+                .into_iter()
+                .filter(|name| !name.starts_with('.'))
+                .collect())
+        }
+    }
+
+    pub struct FsStorage;
+
+    impl Storage for FsStorage {
+        fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+            fs::read(path)
+        }
+
+        fn reader(&self, path: &str) -> Result<Box<dyn Read>, Error> {
+            Ok(Box::new(fs::File::open(path)?))
+        }
+
+        fn size(&self, path: &str) -> Result<u64, Error> {
+            Ok(fs::metadata(path)?.len())
+        }
+
+        fn exists(&self, path: &str) -> bool {
+            Path::new(path).exists()
+        }
+
+        fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                if let Ok(name) = entry?.file_name().into_string() {
+                    names.push(name);
+                }
+            }
+
+            Ok(names)
+        }
+
+        fn write(&self, path: &str, contents: &[u8]) -> Result<(), Error> {
+            create_parent_dir(path)?;
+            file_system::write_file_atomic(path, contents)
+        }
+
+        fn write_with(
+            &self,
+            path: &str,
+            write: &mut dyn FnMut(&mut dyn Write) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            create_parent_dir(path)?;
+            file_system::write_file_atomic_with(path, |file| {
+                let mut writer = BufWriter::new(file);
+                write(&mut writer)?;
+                writer.flush()
+            })
+        }
+
+        // The files are staged next to their targets first, so a failure
+        // before the renames leaves every target as it was. Each rename is
+        // atomic, the commit as a whole is not: a crash between two renames
+        // leaves the earlier files replaced and the later ones staged.
+        fn commit(&self, writes: &[(&str, &[u8])]) -> Result<(), Error> {
+            let mut staged = Vec::with_capacity(writes.len());
+            for (path, contents) in writes.iter() {
+                let staged_path = staged_path(path)?;
+                create_parent_dir(path)?;
+                if let Err(why) = file_system::write_file_atomic(&staged_path, contents) {
+                    for (staged_path, _) in staged.iter() {
+                        let _ = fs::remove_file(staged_path);
+                    }
+                    return Err(why);
+                }
+                staged.push((staged_path, *path));
+            }
+
+            for (staged_path, path) in staged.iter() {
+                fs::rename(staged_path, path)?;
+                file_system::sync_parent_dir(Path::new(path))?;
+            }
+            Ok(())
+        }
+
+        fn delete(&self, path: &str) -> Result<(), Error> {
+            if Path::new(path).is_dir() {
+                fs::remove_dir_all(path)?;
+            } else {
+                fs::remove_file(path)?;
+            }
+            file_system::sync_parent_dir(Path::new(path))
+        }
+
+        // Overwrites the file with random bytes and syncs it before removing
+        // it. File systems that copy on write or journal data, and flash
+        // drives that remap blocks, may still keep the old contents elsewhere.
+        fn wipe(&self, path: &str) -> Result<(), Error> {
+            let mut file = fs::OpenOptions::new().write(true).open(path)?;
+            let mut left = file.metadata()?.len();
+            let mut random = [0u8; 4096];
+            while left > 0 {
+                let len = left.min(random.len() as u64) as usize;
+                secure_rand_bytes(&mut random[..len]).map_err(Error::other)?;
+                file.write_all(&random[..len])?;
+                left -= len as u64;
+            }
+            file.sync_all()?;
+            drop(file);
+
+            self.delete(path)
+        }
+
+        // Setting the times also sets each file's change time to now, so that
+        // does not single out the newest file either.
+        fn level_times(&self, dir: &str) -> Result<(), Error> {
+            if !Path::new(dir).is_dir() {
+                return Ok(());
+            }
+            let now = SystemTime::now();
+            let times = fs::FileTimes::new().set_accessed(now).set_modified(now);
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    fs::File::open(entry.path())?.set_times(times)?;
+                }
+            }
+
+            Ok(())
+        }
+
+        fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+            create_parent_dir(to)?;
+            fs::rename(from, to)?;
+            file_system::sync_parent_dir(Path::new(from))?;
+            file_system::sync_parent_dir(Path::new(to))
+        }
+
+        fn prune(&self, dir: &str) -> Result<(), Error> {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    self.prune(&path.to_string_lossy())?;
+                }
+            }
+            if fs::read_dir(dir)?.next().is_none() {
+                fs::remove_dir(dir)?;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn create_parent_dir(path: &str) -> Result<(), Error> {
+        match Path::new(path).parent() {
+            Some(parent) => file_system::create_dir_all_durable(parent),
+            None => Ok(()),
+        }
+    }
+
+    fn staged_path(path: &str) -> Result<String, Error> {
+        let path = Path::new(path);
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => Ok(path
+                .with_file_name(format!(".{}.staged", name))
+                .to_string_lossy()
+                .into_owned()),
+            None => Err(Error::new(ErrorKind::InvalidInput, "Invalid file path")),
+        }
+    }
+
+    // Directories only exist through the files below them.
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        files: Mutex<BTreeMap<String, Vec<u8>>>,
+    }
+
+    impl MemoryStorage {
+        pub fn new() -> Self {
+            Self::default()
+        }
+    }
+
+    impl Storage for MemoryStorage {
+        fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+            match self.files.lock().unwrap().get(&normalize(path)) {
+                Some(contents) => Ok(contents.clone()),
+                None => Err(not_found(path)),
+            }
+        }
+
+        fn reader(&self, path: &str) -> Result<Box<dyn Read>, Error> {
+            Ok(Box::new(Cursor::new(self.read(path)?)))
+        }
+
+        fn size(&self, path: &str) -> Result<u64, Error> {
+            match self.files.lock().unwrap().get(&normalize(path)) {
+                Some(contents) => Ok(contents.len() as u64),
+                None => Err(not_found(path)),
+            }
+        }
+
+        fn exists(&self, path: &str) -> bool {
+            contains_entry(&self.files.lock().unwrap(), path)
+        }
+
+        fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+            list_entries(&self.files.lock().unwrap(), dir)
+        }
+
+        fn write(&self, path: &str, contents: &[u8]) -> Result<(), Error> {
+            self.files
+                .lock()
+                .unwrap()
+                .insert(normalize(path), contents.to_vec());
+            Ok(())
+        }
+
+        fn write_with(
+            &self,
+            path: &str,
+            write: &mut dyn FnMut(&mut dyn Write) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            let mut contents = Vec::new();
+            write(&mut contents)?;
+            self.write(path, &contents)
+        }
+
+        fn commit(&self, writes: &[(&str, &[u8])]) -> Result<(), Error> {
+            let mut files = self.files.lock().unwrap();
+            for (path, contents) in writes.iter() {
+                files.insert(normalize(path), contents.to_vec());
+            }
+            Ok(())
+        }
+
+        fn delete(&self, path: &str) -> Result<(), Error> {
+            remove_entries(&mut self.files.lock().unwrap(), path)
+        }
+
+        fn wipe(&self, path: &str) -> Result<(), Error> {
+            let mut files = self.files.lock().unwrap();
+            match files.get_mut(&normalize(path)) {
+                Some(contents) => contents.zeroize(),
+                None => return Err(not_found(path)),
+            }
+            remove_entries(&mut files, path)
+        }
+
+        fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+            rename_entries(&mut self.files.lock().unwrap(), from, to)
+        }
+
+        fn prune(&self, _dir: &str) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    // Copies every file below `dirs` from one backend to the other in a single
+    // commit and returns how many there were. Directories that do not exist
+    // are skipped.
+    pub fn copy(from: &dyn Storage, to: &dyn Storage, dirs: &[&str]) -> Result<usize, Error> {
+        let mut files = Vec::new();
+        for path in collect_files(from, dirs)? {
+            let contents = from.read(&path)?;
+            files.push((path, contents));
+        }
+
+        let writes: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(path, contents)| (path.as_str(), contents.as_slice()))
+            .collect();
+        to.commit(&writes)?;
+        Ok(files.len())
+    }
+
+    // Wipes every file below `dirs`, then removes the directories, and
+    // returns how many files there were. Directories that do not exist are
+    // skipped.
+    pub fn wipe_dirs(storage: &dyn Storage, dirs: &[&str]) -> Result<usize, Error> {
+        let files = collect_files(storage, dirs)?;
+        for path in files.iter() {
+            storage.wipe(path)?;
+        }
+        for dir in dirs.iter() {
+            if storage.exists(dir) {
+                storage.delete(dir)?;
+            }
+        }
+
+        Ok(files.len())
+    }
+
+    fn collect_files(storage: &dyn Storage, dirs: &[&str]) -> Result<Vec<String>, Error> {
+        let mut files = Vec::new();
+        for dir in dirs.iter() {
+            if storage.exists(dir) {
+                collect_files_below(storage, dir, &mut files)?;
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn collect_files_below(
+        storage: &dyn Storage,
+        path: &str,
+        files: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        match storage.list(path) {
+            Ok(names) => {
+                for name in names {
+                    collect_files_below(storage, &format!("{}/{}", path, name), files)?;
+                }
+            }
+            Err(_) => files.push(path.to_string()),
+        }
+
+        Ok(())
+    }
+
+    // Backends that keep files in a map keyed by path share the handling of
+    // the directories those paths imply.
+    pub fn contains_entry<V>(entries: &BTreeMap<String, V>, path: &str) -> bool {
+        let path = normalize(path);
+        entries.contains_key(&path) || entries.keys().any(|entry| is_below(entry, &path))
+    }
+
+    pub fn list_entries<V>(entries: &BTreeMap<String, V>, dir: &str) -> Result<Vec<String>, Error> {
+        let dir = normalize(dir);
+        let mut names: Vec<String> = Vec::new();
+        for entry in entries.keys() {
+            if is_below(entry, &dir) {
+                let name = entry[dir.len() + 1..].split('/').next().unwrap();
+                if names.last().map(String::as_str) != Some(name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        if names.is_empty() {
+            return Err(not_found(&dir));
+        }
+
+        Ok(names)
+    }
+
+    pub fn remove_entries<V>(entries: &mut BTreeMap<String, V>, path: &str) -> Result<(), Error> {
+        let path = normalize(path);
+        let count = entries.len();
+        entries.retain(|entry, _| *entry != path && !is_below(entry, &path));
+        if entries.len() == count {
+            return Err(not_found(&path));
+        }
+
+        Ok(())
+    }
+
+    pub fn rename_entries<V>(
+        entries: &mut BTreeMap<String, V>,
+        from: &str,
+        to: &str,
+    ) -> Result<(), Error> {
+        let (from, to) = (normalize(from), normalize(to));
+        let moved: Vec<String> = entries
+            .keys()
+            .filter(|entry| **entry == from || is_below(entry, &from))
+            .cloned()
+            .collect();
+        if moved.is_empty() {
+            return Err(not_found(&from));
+        }
+        if entries.contains_key(&to) && moved.len() == 1 && moved[0] == from {
+            entries.remove(&to);
+        } else if entries.keys().any(|entry| is_below(entry, &to)) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} is not empty", to),
+            ));
+        }
+
+        for entry in moved {
+            let value = entries.remove(&entry).unwrap();
+            entries.insert(format!("{}{}", to, &entry[from.len()..]), value);
+        }
+        Ok(())
+    }
+
+    // Paths are built by concatenation, so "./a//b/" and "a/b" name the same
+    // entry.
+    pub fn normalize(path: &str) -> String {
+        let parts: Vec<&str> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        if path.starts_with('/') {
+            format!("/{}", parts.join("/"))
+        } else {
+            parts.join("/")
+        }
+    }
+
+    fn is_below(file: &str, dir: &str) -> bool {
+        file.len() > dir.len() + 1 && file.starts_with(dir) && file.as_bytes()[dir.len()] == b'/'
+    }
+
+    pub fn not_found(path: &str) -> Error {
+        Error::new(ErrorKind::NotFound, format!("{} does not exist", path))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The same checks run against every backend, under `root`.
+        fn check_entries(storage: &dyn Storage, root: &str) {
+            let path = |name: &str| format!("{}/{}", root, name);
+            storage.write(&path("a/one"), b"1").unwrap();
+            storage.write(&path("a/two"), b"2").unwrap();
+            storage.write(&path("a/sub/three"), b"3").unwrap();
+            storage.write(&path("b/four"), b"4").unwrap();
+
+            let mut names = storage.list(&path("a")).unwrap();
+            names.sort();
+            assert_eq!(names, vec!["one", "sub", "two"]);
+            assert_eq!(storage.list(&path("a/sub")).unwrap(), vec!["three"]);
+            assert!(storage.list(&path("missing")).is_err());
+
+            storage.rename(&path("a/one"), &path("a/uno")).unwrap();
+            assert!(!storage.exists(&path("a/one")));
+            assert_eq!(storage.read(&path("a/uno")).unwrap(), b"1");
+            // A file replaces the one it is renamed over.
+            storage.rename(&path("a/uno"), &path("a/two")).unwrap();
+            assert_eq!(storage.read(&path("a/two")).unwrap(), b"1");
+            // A directory moves with everything in it, but not onto another
+            // one that holds anything.
+            storage.rename(&path("a/sub"), &path("c/sub")).unwrap();
+            assert!(!storage.exists(&path("a/sub")));
+            assert_eq!(storage.read(&path("c/sub/three")).unwrap(), b"3");
+            assert!(storage.rename(&path("c"), &path("b")).is_err());
+            assert_eq!(storage.read(&path("b/four")).unwrap(), b"4");
+            assert!(storage.rename(&path("missing"), &path("d")).is_err());
+
+            storage.delete(&path("c")).unwrap();
+            assert!(!storage.exists(&path("c/sub/three")));
+            assert!(storage.list(&path("c")).is_err());
+            assert!(storage.delete(&path("missing")).is_err());
+            assert_eq!(storage.list(&path("a")).unwrap(), vec!["two"]);
+
+            storage.wipe(&path("b/four")).unwrap();
+            assert!(!storage.exists(&path("b/four")));
+            assert!(storage.wipe(&path("b/four")).is_err());
+        }
+
+        #[test]
+        fn files_keep_entries() {
+            let root = std::env::temp_dir().join(format!("storage-{}", std::process::id()));
+            let root = root.to_string_lossy();
+            check_entries(&FsStorage, &root);
+            FsStorage.delete(&root).unwrap();
+        }
+
+        #[test]
+        fn files_get_the_same_times() {
+            let root = std::env::temp_dir().join(format!("levelled-{}", std::process::id()));
+            let root = root.to_string_lossy();
+            let (old, new) = (format!("{}/old", root), format!("{}/new", root));
+            FsStorage.write(&old, b"1").unwrap();
+            FsStorage.write(&new, b"2").unwrap();
+            let times = fs::FileTimes::new().set_modified(std::time::UNIX_EPOCH);
+            fs::File::open(&old).unwrap().set_times(times).unwrap();
+
+            FsStorage.level_times(&root).unwrap();
+            let modified = |path: &str| fs::metadata(path).unwrap().modified().unwrap();
+            assert_eq!(modified(&old), modified(&new));
+            assert!(modified(&old) > std::time::UNIX_EPOCH);
+            FsStorage.delete(&root).unwrap();
+        }
+
+        #[test]
+        fn memory_keeps_entries() {
+            let storage = MemoryStorage::new();
+            check_entries(&storage, "memory");
+            // Directories only exist while they hold files.
+            assert!(!storage.exists("memory/c"));
+            assert_eq!(storage.read("./memory//a/two/").unwrap(), b"1");
+        }
+
+        #[test]
+        fn copies_and_wipes_directories() {
+            let from = MemoryStorage::new();
+            from.write("old/keys/a", b"1").unwrap();
+            from.write("old/logs/x/b", b"2").unwrap();
+            from.write("other/c", b"3").unwrap();
+            let dirs = ["old/keys", "old/logs", "missing"];
+
+            let to = MemoryStorage::new();
+            assert_eq!(copy(&from, &to, &dirs).unwrap(), 2);
+            assert_eq!(to.read("old/logs/x/b").unwrap(), b"2");
+            assert!(!to.exists("other"));
+
+            assert_eq!(wipe_dirs(&from, &dirs).unwrap(), 2);
+            assert!(!from.exists("old"));
+            assert_eq!(from.read("other/c").unwrap(), b"3");
+        }
+    }
+}
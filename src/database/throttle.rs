@@ -0,0 +1,283 @@
+// Failed logins are counted per user in the key directory as
+//
+//   <failures> <unix time of the last failure>
+//
+// and every failure doubles the wait before the next attempt, starting at
+// LOGIN_BACKOFF_SECS (1) and capped at LOGIN_BACKOFF_MAX_SECS (3600). When
+// LOGIN_LOCKOUT is set, that many failures in a row lock the password slots
+// until the account is recovered. A successful login clears the count. Wrong
+// passwords of sealed logs and of the vault are counted the same way, in
+// records of their own, but never lock.
+//
+// A record is written before any password is known, so it cannot be sealed:
+// it can be rolled back to an older copy or edited without notice. Removing a
+// login record, or leaving it unreadable, counts as reaching the limit. The
+// other records start over instead, so that a vault copied without its record
+// opens as usual.
+pub mod throttle {
+    use crate::database::keychain::key_chain;
+    use crate::database::storage::storage::{Database, FsStorage, Storage};
+    use crate::utils::utils::file_system;
+    use std::io::{Error, ErrorKind};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DEFAULT_BACKOFF_SECS: u64 = 1;
+    const DEFAULT_BACKOFF_MAX_SECS: u64 = 3600;
+    const MISSING_RECORD_FAILURES: u32 = u32::MAX;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Status {
+        Open,
+        Wait(Duration),
+        Locked,
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct Failures {
+        pub count: u32,
+        pub last: u64,
+    }
+
+    impl Failures {
+        pub fn to_bytes(&self) -> Vec<u8> {
+            format!("{} {}\n", self.count, self.last).into_bytes()
+        }
+
+        pub fn parse(contents: &str) -> Result<Self, Error> {
+            let corrupt = || Error::new(ErrorKind::InvalidData, "Corrupt login throttle");
+            let (count, last) = contents.trim().split_once(' ').ok_or_else(corrupt)?;
+
+            Ok(Self {
+                count: count.parse::<u32>().map_err(|_| corrupt())?,
+                last: last.parse::<u64>().map_err(|_| corrupt())?,
+            })
+        }
+
+        // `lockout` tells whether LOGIN_LOCKOUT applies.
+        pub fn status(&self, now: u64, lockout: bool) -> Status {
+            if self.count == 0 {
+                return Status::Open;
+            }
+            if let Some(limit) = read_env_u64("LOGIN_LOCKOUT").filter(|_| lockout) {
+                if limit > 0 && u64::from(self.count) >= limit {
+                    return Status::Locked;
+                }
+            }
+
+            let base = read_env_u64("LOGIN_BACKOFF_SECS").unwrap_or(DEFAULT_BACKOFF_SECS);
+            let max = read_env_u64("LOGIN_BACKOFF_MAX_SECS").unwrap_or(DEFAULT_BACKOFF_MAX_SECS);
+            let backoff = 1u64
+                .checked_shl(self.count - 1)
+                .map_or(max, |factor| base.saturating_mul(factor))
+                .min(max);
+            // A clock set back counts as no time having passed.
+            let elapsed = now.saturating_sub(self.last);
+            if elapsed >= backoff {
+                Status::Open
+            } else {
+                Status::Wait(Duration::from_secs(backoff - elapsed))
+            }
+        }
+    }
+
+    // Where failed attempts at one kind of password are counted.
+    pub struct Record {
+        path: String,
+        storage: Arc<dyn Storage>,
+        // Whether a record should exist already, so that a missing one means
+        // it was removed.
+        kept: bool,
+        // Whether LOGIN_LOCKOUT applies and a record that cannot be read
+        // locks; only for logins.
+        lockout: bool,
+    }
+
+    impl Record {
+        // The login password of a user. Every user with key slots has a
+        // record from the time they were created or first logged in.
+        pub fn login(db: &Database, id: &str) -> Self {
+            Self {
+                path: file_system::generate_throttle_path(db.keys_dir(), id),
+                storage: db.storage().clone(),
+                kept: key_chain::has_slots(db, id),
+                lockout: true,
+            }
+        }
+
+        // The passwords of a user's sealed logs, shared by all of them.
+        pub fn log_passwords(db: &Database, id: &str) -> Self {
+            Self {
+                path: file_system::generate_log_throttle_path(db.keys_dir(), id),
+                storage: db.storage().clone(),
+                kept: false,
+                lockout: false,
+            }
+        }
+
+        // The password of the vault at `vault_path`, counted in a file next
+        // to it since the vault cannot be read without the password. The
+        // vault has no recovery key, so it never locks.
+        pub fn vault(vault_path: &str) -> Self {
+            Self {
+                path: format!("{}.throttle", vault_path),
+                storage: Arc::new(FsStorage),
+                kept: false,
+                lockout: false,
+            }
+        }
+
+        // A login record that cannot be read locks the password rather than
+        // letting guesses through.
+        pub fn status(&self) -> Status {
+            match self.load() {
+                Ok(Some(failures)) => failures.status(file_system::unix_time(), self.lockout),
+                Ok(None) => Status::Open,
+                Err(_) if self.lockout => Status::Locked,
+                Err(_) => Status::Open,
+            }
+        }
+
+        pub fn record_failure(&self) -> Result<(), Error> {
+            let mut failures = match self.load() {
+                Ok(failures) => failures.unwrap_or_default(),
+                Err(_) if !self.lockout => Failures::default(),
+                Err(why) => return Err(why),
+            };
+            failures.count = failures.count.saturating_add(1);
+            failures.last = file_system::unix_time();
+
+            self.save(&failures)
+        }
+
+        // Clears the count, keeping the record itself so that removing it
+        // does not go unnoticed.
+        pub fn reset(&self) -> Result<(), Error> {
+            match self.read() {
+                Ok(Some(failures)) if failures.count == 0 => Ok(()),
+                _ => self.save(&Failures::default()),
+            }
+        }
+
+        // A record missing where one should be kept counts as more failures
+        // than any limit, starting now, and is written back so the wait does
+        // not start over every time.
+        fn load(&self) -> Result<Option<Failures>, Error> {
+            match self.read()? {
+                None if self.kept => {
+                    let failures = Failures {
+                        count: MISSING_RECORD_FAILURES,
+                        last: file_system::unix_time(),
+                    };
+                    self.save(&failures)?;
+                    Ok(Some(failures))
+                }
+                failures => Ok(failures),
+            }
+        }
+
+        fn read(&self) -> Result<Option<Failures>, Error> {
+            match self.storage.read(&self.path) {
+                Ok(contents) => Failures::parse(&String::from_utf8_lossy(&contents)).map(Some),
+                Err(why) if why.kind() == ErrorKind::NotFound => Ok(None),
+                Err(why) => Err(why),
+            }
+        }
+
+        fn save(&self, failures: &Failures) -> Result<(), Error> {
+            self.storage.write(&self.path, &failures.to_bytes())
+        }
+    }
+
+    fn read_env_u64(var_name: &str) -> Option<u64> {
+        file_system::get_env_var(var_name)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::database::storage::storage::MemoryStorage;
+        use crate::encryption::slots::slots::{Slot, SlotKind, SALT_SIZE};
+        use std::fs;
+        use std::path::Path;
+
+        fn temp_path(name: &str) -> String {
+            let dir = std::env::temp_dir().join(format!("throttle-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            dir.join(name).to_string_lossy().into_owned()
+        }
+
+        #[test]
+        fn counts_failures_and_resets() {
+            let vault_path = temp_path("counts");
+            fs::write(&vault_path, b"vault").unwrap();
+            let record = Record::vault(&vault_path);
+            record.reset().unwrap();
+            assert_eq!(record.status(), Status::Open);
+
+            record.record_failure().unwrap();
+            assert!(matches!(record.status(), Status::Wait(_)));
+            record.reset().unwrap();
+            assert_eq!(record.status(), Status::Open);
+            assert!(Path::new(&format!("{}.throttle", vault_path)).exists());
+        }
+
+        #[test]
+        fn a_removed_login_record_counts_as_the_limit() {
+            let storage = Arc::new(MemoryStorage::new());
+            let db = Database::new(storage.clone(), "logs", "keys");
+            // A user with a slot has had a record since it was created.
+            let slot = Slot {
+                kind: SlotKind::Password,
+                salt: [0u8; SALT_SIZE],
+                sealed: vec![0u8; 32],
+            };
+            storage
+                .write("keys/user/slots/0.slot", &slot.to_bytes())
+                .unwrap();
+            let record = Record::login(&db, "user");
+            assert!(matches!(record.status(), Status::Wait(_)));
+            // The wait is kept rather than starting over.
+            let failures = Failures::parse(&String::from_utf8_lossy(
+                &storage.read("keys/user/throttle.txt").unwrap(),
+            ))
+            .unwrap();
+            assert_eq!(failures.count, MISSING_RECORD_FAILURES);
+            assert!(matches!(record.status(), Status::Wait(_)));
+
+            storage.write("keys/user/throttle.txt", b"garbage").unwrap();
+            assert_eq!(record.status(), Status::Locked);
+            assert!(record.record_failure().is_err());
+        }
+
+        #[test]
+        fn a_vault_record_starts_over() {
+            // A vault moved without its record opens as usual.
+            let vault_path = temp_path("moved");
+            fs::write(&vault_path, b"vault").unwrap();
+            let record = Record::vault(&vault_path);
+            assert_eq!(record.status(), Status::Open);
+            assert!(!Path::new(&format!("{}.throttle", vault_path)).exists());
+
+            let unreadable = temp_path("unreadable");
+            fs::write(&unreadable, b"vault").unwrap();
+            fs::write(format!("{}.throttle", unreadable), b"garbage").unwrap();
+            let record = Record::vault(&unreadable);
+            assert_eq!(record.status(), Status::Open);
+            record.record_failure().unwrap();
+            assert!(matches!(record.status(), Status::Wait(_)));
+        }
+
+        #[test]
+        fn only_logins_lock_out() {
+            let failures = Failures {
+                count: MISSING_RECORD_FAILURES,
+                last: 0,
+            };
+            assert!(matches!(failures.status(0, false), Status::Wait(_)));
+            assert_eq!(failures.status(u64::MAX, false), Status::Open);
+        }
+    }
+}
@@ -0,0 +1,715 @@
+// A vault keeps a whole database, keys and logs alike, in a single file (all
+// integers little endian):
+//
+//   magic "CEVT" | version u8 | kdf u8 | iterations u32 | memory u32
+//   | salt [u8; 16] | zeros up to 32 | commit A [u8; 32] | commit B [u8; 32]
+//   | records ...
+//
+// A commit is
+//
+//   generation u64 | table offset u64 | table length u64 | checksum [u8; 8]
+//
+// and points to the allocation table, which maps every path to the offset and
+// length of its record. The table and every record are sealed with a key
+// derived from the vault password, so without it the file shows neither names
+// nor contents, only how many bytes are in use. A record is sealed to its path
+// and the table to its generation, so neither can be swapped for another one. A change writes its records
+// and a new table into space the current table does not use, and only then
+// overwrites the older of the two commits, so a vault cut off mid-write opens
+// at its last complete commit. Space the new table no longer uses is zeroed
+// and taken again by later writes.
+pub mod vault {
+    use crate::database::storage::storage::{self, Storage};
+    use crate::database::throttle::throttle::{Record, Status};
+    use crate::encryption::container::container::{KdfAlgorithm, KdfParams};
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::kdf_policy::kdf_policy::KdfPolicy;
+    use crate::encryption::slots::slots::SALT_SIZE;
+    use crate::utils::utils::file_system;
+    use orion::errors::UnknownCryptoError;
+    use orion::hazardous::aead::xchacha20poly1305::{self, Nonce, SecretKey};
+    use orion::hazardous::hash::blake2b::Blake2b;
+    use orion::hazardous::mac::poly1305::POLY1305_OUTSIZE;
+    use orion::hazardous::stream::xchacha20::XCHACHA_NONCESIZE;
+    use orion::kdf;
+    use orion::util::secure_rand_bytes;
+    use std::collections::BTreeMap;
+    use std::convert::TryInto;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::prelude::*;
+    use std::io::{Cursor, Error, ErrorKind, SeekFrom};
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    const MAGIC: [u8; 4] = *b"CEVT";
+    const VERSION: u8 = 2;
+    const COMMIT_OFFSETS: [u64; 2] = [32, 64];
+    const COMMIT_SIZE: usize = 32;
+    const DATA_START: u64 = 96;
+    // Nonce and tag added by `seal`.
+    const SEAL_OVERHEAD: u64 = (XCHACHA_NONCESIZE + POLY1305_OUTSIZE) as u64;
+    // The table is padded so its size only hints at the number of files.
+    const TABLE_BLOCK: usize = 4096;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Extent {
+        offset: u64,
+        len: u64,
+    }
+
+    impl Extent {
+        fn end(&self) -> u64 {
+            self.offset + self.len
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Commit {
+        generation: u64,
+        table: Extent,
+    }
+
+    impl Commit {
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::with_capacity(COMMIT_SIZE);
+            bytes.extend_from_slice(&self.generation.to_le_bytes());
+            bytes.extend_from_slice(&self.table.offset.to_le_bytes());
+            bytes.extend_from_slice(&self.table.len.to_le_bytes());
+            bytes.extend_from_slice(&checksum(&bytes));
+            bytes
+        }
+
+        // A commit that was torn while being written fails its checksum.
+        fn from_bytes(bytes: &[u8]) -> Option<Self> {
+            if checksum(&bytes[..24]) != bytes[24..COMMIT_SIZE] {
+                return None;
+            }
+
+            Some(Self {
+                generation: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+                table: Extent {
+                    offset: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+                    len: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+                },
+            })
+        }
+    }
+
+    struct State {
+        file: File,
+        generation: u64,
+        table_extent: Extent,
+        table: BTreeMap<String, Extent>,
+    }
+
+    pub struct VaultStorage {
+        key: kdf::SecretKey,
+        state: Mutex<State>,
+    }
+
+    impl VaultStorage {
+        // Fails when `path` already exists.
+        pub fn create(path: &str, password: &str) -> Result<Self, Error> {
+            let params = KdfPolicy::from_env().calibrate().map_err(crypto_error)?;
+            let salt = kdf::Salt::default();
+            let key = crypto::derive_password_key(password, salt.as_ref(), &params)
+                .map_err(crypto_error)?;
+
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+            let vault = Self {
+                key,
+                state: Mutex::new(State {
+                    file: file.try_clone()?,
+                    generation: 0,
+                    table_extent: Extent {
+                        offset: DATA_START,
+                        len: 0,
+                    },
+                    table: BTreeMap::new(),
+                }),
+            };
+
+            let mut header = Vec::with_capacity(DATA_START as usize);
+            header.extend_from_slice(&MAGIC);
+            header.push(VERSION);
+            header.push(params.algorithm as u8);
+            header.extend_from_slice(&params.iterations.to_le_bytes());
+            header.extend_from_slice(&params.memory.to_le_bytes());
+            header.extend_from_slice(salt.as_ref());
+            header.resize(DATA_START as usize, 0);
+            let created = file
+                .write_all(&header)
+                .and_then(|_| vault.commit_table(&mut vault.state.lock().unwrap(), BTreeMap::new()))
+                .and_then(|_| file_system::sync_parent_dir(Path::new(path)));
+            if let Err(why) = created {
+                let _ = fs::remove_file(path);
+                return Err(why);
+            }
+            Record::vault(path).reset()?;
+
+            Ok(vault)
+        }
+
+        // Wrong passwords are throttled like logins, see `throttle`.
+        pub fn open(path: &str, password: &str) -> Result<Self, Error> {
+            let record = Record::vault(path);
+            if record.status() != Status::Open {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "Too many wrong vault passwords, try again later",
+                ));
+            }
+            let opened = Self::open_unthrottled(path, password);
+            match &opened {
+                Ok(_) => record.reset()?,
+                Err(why) if why.kind() == ErrorKind::PermissionDenied => record.record_failure()?,
+                Err(_) => (),
+            }
+
+            opened
+        }
+
+        fn open_unthrottled(path: &str, password: &str) -> Result<Self, Error> {
+            let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+            let mut header = [0u8; DATA_START as usize];
+            if file.read_exact(&mut header).is_err() || header[0..4] != MAGIC {
+                return Err(Error::new(ErrorKind::InvalidData, "Not a vault"));
+            }
+            if header[4] != VERSION || header[5] != KdfAlgorithm::Argon2i as u8 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Unsupported vault version",
+                ));
+            }
+            let params = KdfParams {
+                algorithm: KdfAlgorithm::Argon2i,
+                iterations: u32::from_le_bytes(header[6..10].try_into().unwrap()),
+                memory: u32::from_le_bytes(header[10..14].try_into().unwrap()),
+            };
+            let salt = &header[14..14 + SALT_SIZE];
+            let key = crypto::derive_password_key(password, salt, &params).map_err(crypto_error)?;
+
+            // A commit torn mid-write fails its checksum, which leaves the
+            // other one, the last that was completed.
+            let commit = COMMIT_OFFSETS
+                .iter()
+                .filter_map(|offset| {
+                    let offset = *offset as usize;
+                    Commit::from_bytes(&header[offset..offset + COMMIT_SIZE])
+                })
+                .max_by_key(|commit| commit.generation)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Damaged vault"))?;
+            let table = read_table(&mut file, &key, &commit).map_err(|_| {
+                Error::new(
+                    ErrorKind::PermissionDenied,
+                    "Wrong vault password or damaged vault",
+                )
+            })?;
+
+            Ok(Self {
+                key,
+                state: Mutex::new(State {
+                    file,
+                    generation: commit.generation,
+                    table_extent: commit.table,
+                    table,
+                }),
+            })
+        }
+
+        // Seals `records` into free space, then commits `table` with them
+        // added to it.
+        fn write_records(
+            &self,
+            state: &mut State,
+            mut table: BTreeMap<String, Extent>,
+            records: &[(&str, &[u8])],
+        ) -> Result<(), Error> {
+            let mut used = used_extents(state);
+            for (path, contents) in records.iter() {
+                let path = storage::normalize(path);
+                let sealed = seal(&self.key, contents, &record_ad(&path)).map_err(crypto_error)?;
+                let extent = allocate(&mut used, sealed.len() as u64);
+                write_at(&mut state.file, extent.offset, &sealed)?;
+                table.insert(path, extent);
+            }
+
+            self.commit_table(state, table)
+        }
+
+        fn commit_table(
+            &self,
+            state: &mut State,
+            table: BTreeMap<String, Extent>,
+        ) -> Result<(), Error> {
+            let generation = state.generation + 1;
+            let sealed = seal(
+                &self.key,
+                &table_to_bytes(generation, &table),
+                &table_ad(generation),
+            )
+            .map_err(crypto_error)?;
+            let mut used = used_extents(state);
+            used.extend(table.values().copied());
+            let table_extent = allocate(&mut used, sealed.len() as u64);
+            write_at(&mut state.file, table_extent.offset, &sealed)?;
+            state.file.sync_data()?;
+
+            let commit = Commit {
+                generation,
+                table: table_extent,
+            };
+            let slot = COMMIT_OFFSETS[(generation % 2) as usize];
+            write_at(&mut state.file, slot, &commit.to_bytes())?;
+            state.file.sync_data()?;
+
+            let freed: Vec<Extent> = used_extents(state)
+                .into_iter()
+                .filter(|extent| *extent != table_extent && !table.values().any(|e| e == extent))
+                .collect();
+            state.generation = generation;
+            state.table_extent = table_extent;
+            state.table = table;
+
+            release(state, &freed)
+        }
+
+        fn read_record(&self, state: &mut State, path: &str) -> Result<Vec<u8>, Error> {
+            let path = storage::normalize(path);
+            let extent = match state.table.get(&path) {
+                Some(extent) => *extent,
+                None => return Err(storage::not_found(&path)),
+            };
+
+            let sealed = read_at(&mut state.file, extent)?;
+            open(&self.key, &sealed, &record_ad(&path)).map_err(crypto_error)
+        }
+    }
+
+    impl Storage for VaultStorage {
+        fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+            self.read_record(&mut self.state.lock().unwrap(), path)
+        }
+
+        fn reader(&self, path: &str) -> Result<Box<dyn Read>, Error> {
+            Ok(Box::new(Cursor::new(self.read(path)?)))
+        }
+
+        fn size(&self, path: &str) -> Result<u64, Error> {
+            match self
+                .state
+                .lock()
+                .unwrap()
+                .table
+                .get(&storage::normalize(path))
+            {
+                Some(extent) => Ok(extent.len - SEAL_OVERHEAD),
+                None => Err(storage::not_found(path)),
+            }
+        }
+
+        fn exists(&self, path: &str) -> bool {
+            storage::contains_entry(&self.state.lock().unwrap().table, path)
+        }
+
+        fn list(&self, dir: &str) -> Result<Vec<String>, Error> {
+            storage::list_entries(&self.state.lock().unwrap().table, dir)
+        }
+
+        fn write(&self, path: &str, contents: &[u8]) -> Result<(), Error> {
+            self.commit(&[(path, contents)])
+        }
+
+        fn write_with(
+            &self,
+            path: &str,
+            write: &mut dyn FnMut(&mut dyn Write) -> Result<(), Error>,
+        ) -> Result<(), Error> {
+            let mut contents = Vec::new();
+            write(&mut contents)?;
+            self.write(path, &contents)
+        }
+
+        fn commit(&self, writes: &[(&str, &[u8])]) -> Result<(), Error> {
+            let mut state = self.state.lock().unwrap();
+            let table = state.table.clone();
+            self.write_records(&mut state, table, writes)
+        }
+
+        fn delete(&self, path: &str) -> Result<(), Error> {
+            let mut state = self.state.lock().unwrap();
+            let mut table = state.table.clone();
+            storage::remove_entries(&mut table, path)?;
+            self.commit_table(&mut state, table)
+        }
+
+        // `wipe` keeps the default: a deleted record is zeroed as soon as the
+        // table that drops it is committed, see `release`.
+
+        fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+            let mut state = self.state.lock().unwrap();
+            let mut table = state.table.clone();
+            storage::rename_entries(&mut table, from, to)?;
+            // Records are sealed to their paths, so the moved ones are sealed
+            // again under their new paths.
+            let mut moved = Vec::new();
+            for (path, extent) in table.iter() {
+                if state.table.get(path) == Some(extent) {
+                    continue;
+                }
+                let old = state
+                    .table
+                    .iter()
+                    .find(|(_, old)| *old == extent)
+                    .map(|(old, _)| old.clone())
+                    .unwrap();
+                moved.push((path.clone(), self.read_record(&mut state, &old)?));
+            }
+            let records: Vec<(&str, &[u8])> = moved
+                .iter()
+                .map(|(path, contents)| (path.as_str(), contents.as_slice()))
+                .collect();
+            self.write_records(&mut state, table, &records)
+        }
+
+        fn prune(&self, _dir: &str) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    // Everything the committed table refers to, the table included. None of
+    // it may be overwritten before the next commit.
+    fn used_extents(state: &State) -> Vec<Extent> {
+        let mut used: Vec<Extent> = state.table.values().copied().collect();
+        used.push(state.table_extent);
+        used
+    }
+
+    // Takes the first gap that fits, or the end of the file.
+    fn allocate(used: &mut Vec<Extent>, len: u64) -> Extent {
+        used.sort_by_key(|extent| extent.offset);
+        let mut offset = DATA_START;
+        for extent in used.iter() {
+            if extent.offset >= offset + len {
+                break;
+            }
+            offset = offset.max(extent.end());
+        }
+
+        let extent = Extent { offset, len };
+        used.push(extent);
+        extent
+    }
+
+    // Zeroes what is no longer used and gives back the space at the end.
+    fn release(state: &mut State, freed: &[Extent]) -> Result<(), Error> {
+        for extent in freed.iter() {
+            write_at(
+                &mut state.file,
+                extent.offset,
+                &vec![0u8; extent.len as usize],
+            )?;
+        }
+
+        let end = used_extents(state)
+            .iter()
+            .map(Extent::end)
+            .fold(DATA_START, u64::max);
+        if state.file.metadata()?.len() > end {
+            state.file.set_len(end)?;
+        }
+        state.file.sync_data()
+    }
+
+    // generation u64 | count u32 | (path length u16 | path | offset u64 | length u64)...
+    // followed by zeros up to a multiple of TABLE_BLOCK.
+    fn table_to_bytes(generation: u64, table: &BTreeMap<String, Extent>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&generation.to_le_bytes());
+        bytes.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        for (path, extent) in table.iter() {
+            bytes.extend_from_slice(&(path.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(path.as_bytes());
+            bytes.extend_from_slice(&extent.offset.to_le_bytes());
+            bytes.extend_from_slice(&extent.len.to_le_bytes());
+        }
+        bytes.resize(bytes.len().div_ceil(TABLE_BLOCK) * TABLE_BLOCK, 0);
+        bytes
+    }
+
+    fn parse_table(bytes: &[u8], generation: u64) -> Result<BTreeMap<String, Extent>, Error> {
+        let corrupt = || Error::new(ErrorKind::InvalidData, "Corrupt vault table");
+        let mut cursor = Cursor::new(bytes);
+        if read_u64(&mut cursor)? != generation {
+            return Err(corrupt());
+        }
+
+        let mut table = BTreeMap::new();
+        let mut count = [0u8; 4];
+        cursor.read_exact(&mut count)?;
+        for _ in 0..u32::from_le_bytes(count) {
+            let mut len = [0u8; 2];
+            cursor.read_exact(&mut len)?;
+            let mut path = vec![0u8; usize::from(u16::from_le_bytes(len))];
+            cursor.read_exact(&mut path)?;
+            let path = String::from_utf8(path).map_err(|_| corrupt())?;
+            let extent = Extent {
+                offset: read_u64(&mut cursor)?,
+                len: read_u64(&mut cursor)?,
+            };
+            if extent.offset < DATA_START || extent.len < SEAL_OVERHEAD {
+                return Err(corrupt());
+            }
+            table.insert(path, extent);
+        }
+
+        Ok(table)
+    }
+
+    fn read_table(
+        file: &mut File,
+        key: &kdf::SecretKey,
+        commit: &Commit,
+    ) -> Result<BTreeMap<String, Extent>, Error> {
+        let sealed = read_at(file, commit.table)?;
+        let bytes = open(key, &sealed, &table_ad(commit.generation)).map_err(crypto_error)?;
+        parse_table(&bytes, commit.generation)
+    }
+
+    // nonce [u8; 24] | ciphertext + poly1305 tag, with `ad` as associated data.
+    fn seal(
+        key: &kdf::SecretKey,
+        contents: &[u8],
+        ad: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let mut sealed = vec![0u8; SEAL_OVERHEAD as usize + contents.len()];
+        let (nonce, body) = sealed.split_at_mut(XCHACHA_NONCESIZE);
+        secure_rand_bytes(nonce)?;
+        xchacha20poly1305::seal(
+            &SecretKey::from_slice(key.unprotected_as_bytes())?,
+            &Nonce::from_slice(nonce)?,
+            contents,
+            Some(ad),
+            body,
+        )?;
+        Ok(sealed)
+    }
+
+    fn open(key: &kdf::SecretKey, sealed: &[u8], ad: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        if sealed.len() < SEAL_OVERHEAD as usize {
+            return Err(UnknownCryptoError);
+        }
+        let (nonce, body) = sealed.split_at(XCHACHA_NONCESIZE);
+        let mut contents = vec![0u8; body.len() - POLY1305_OUTSIZE];
+        xchacha20poly1305::open(
+            &SecretKey::from_slice(key.unprotected_as_bytes())?,
+            &Nonce::from_slice(nonce)?,
+            body,
+            Some(ad),
+            &mut contents,
+        )?;
+        Ok(contents)
+    }
+
+    fn record_ad(path: &str) -> Vec<u8> {
+        [b"record ".as_slice(), path.as_bytes()].concat()
+    }
+
+    fn table_ad(generation: u64) -> Vec<u8> {
+        [b"table ".as_slice(), &generation.to_le_bytes()].concat()
+    }
+
+    fn read_u64(reader: &mut impl Read) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_at(file: &mut File, extent: Extent) -> Result<Vec<u8>, Error> {
+        if extent.end() > file.metadata()?.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Truncated vault"));
+        }
+
+        let mut contents = vec![0u8; extent.len as usize];
+        file.seek(SeekFrom::Start(extent.offset))?;
+        file.read_exact(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn write_at(file: &mut File, offset: u64, contents: &[u8]) -> Result<(), Error> {
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(contents)
+    }
+
+    fn checksum(bytes: &[u8]) -> Vec<u8> {
+        let mut state = Blake2b::new(None, 8).unwrap();
+        state.update(bytes).unwrap();
+        state.finalize().unwrap().as_ref().to_vec()
+    }
+
+    fn crypto_error(_: UnknownCryptoError) -> Error {
+        Error::new(ErrorKind::InvalidData, "Vault encryption error")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::env;
+
+        fn create_vault(name: &str) -> (String, VaultStorage) {
+            env::set_var("KDF_TARGET_MS", "0");
+            env::set_var("KDF_MIN_MEMORY", "8");
+            let dir = env::temp_dir().join(format!("vault-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name).to_string_lossy().into_owned();
+            let _ = fs::remove_file(&path);
+            let vault = VaultStorage::create(&path, "vault password").unwrap();
+            (path, vault)
+        }
+
+        fn extent_of(vault: &VaultStorage, path: &str) -> Extent {
+            vault.state.lock().unwrap().table[path]
+        }
+
+        fn read_commit(path: &str, slot: u64) -> Option<Commit> {
+            let header = fs::read(path).unwrap();
+            let slot = slot as usize;
+            Commit::from_bytes(&header[slot..slot + COMMIT_SIZE])
+        }
+
+        #[test]
+        fn allocate_takes_the_first_gap_that_fits() {
+            let extent = |offset, len| Extent { offset, len };
+            let mut used = vec![extent(300, 100), extent(DATA_START, 100)];
+            assert_eq!(allocate(&mut used, 50), extent(196, 50));
+            // What is left before 300 is too small now.
+            assert_eq!(allocate(&mut used, 60), extent(400, 60));
+            assert_eq!(allocate(&mut used, 54), extent(246, 54));
+            assert_eq!(allocate(&mut Vec::new(), 10), extent(DATA_START, 10));
+        }
+
+        #[test]
+        fn commits_alternate_between_the_two_slots() {
+            let (path, vault) = create_vault("commits");
+            let first = vault.state.lock().unwrap().generation;
+            vault.write("keys/a", b"one").unwrap();
+            vault.write("keys/b", b"two").unwrap();
+
+            let generation = vault.state.lock().unwrap().generation;
+            assert_eq!(generation, first + 2);
+            let newest = read_commit(&path, COMMIT_OFFSETS[(generation % 2) as usize]).unwrap();
+            let older = read_commit(&path, COMMIT_OFFSETS[((generation + 1) % 2) as usize]);
+            assert_eq!(newest.generation, generation);
+            assert_eq!(older.unwrap().generation, generation - 1);
+            assert_eq!(newest.table, vault.state.lock().unwrap().table_extent);
+
+            drop(vault);
+            let vault = VaultStorage::open(&path, "vault password").unwrap();
+            assert_eq!(vault.read("keys/a").unwrap(), b"one");
+            assert_eq!(vault.read("keys/b").unwrap(), b"two");
+        }
+
+        #[test]
+        fn release_zeroes_freed_records_and_shrinks_the_file() {
+            let (path, vault) = create_vault("release");
+            vault.write("logs/small", b"small").unwrap();
+            vault.write("logs/large", &[7u8; 10_000]).unwrap();
+            let replaced = extent_of(&vault, "logs/small");
+            vault.write("logs/small", b"replaced").unwrap();
+
+            let raw = fs::read(&path).unwrap();
+            let freed = &raw[replaced.offset as usize..replaced.end() as usize];
+            assert!(freed.iter().all(|byte| *byte == 0));
+
+            let before = fs::metadata(&path).unwrap().len();
+            vault.delete("logs/large").unwrap();
+            let after = fs::metadata(&path).unwrap().len();
+            assert!(after + 10_000 <= before, "{} {}", before, after);
+            let end = used_extents(&vault.state.lock().unwrap())
+                .iter()
+                .map(Extent::end)
+                .max()
+                .unwrap();
+            assert_eq!(after, end);
+            assert_eq!(vault.read("logs/small").unwrap(), b"replaced");
+        }
+
+        #[test]
+        fn records_cannot_be_swapped() {
+            let (_, vault) = create_vault("swapped");
+            vault.write("logs/first", b"first").unwrap();
+            vault.write("logs/other", b"other").unwrap();
+
+            {
+                let mut state = vault.state.lock().unwrap();
+                let first = state.table["logs/first"];
+                let other = state.table["logs/other"];
+                state.table.insert(String::from("logs/first"), other);
+                state.table.insert(String::from("logs/other"), first);
+            }
+            assert!(vault.read("logs/first").is_err());
+            assert!(vault.read("logs/other").is_err());
+        }
+
+        #[test]
+        fn renamed_records_open_under_their_new_paths() {
+            let (path, vault) = create_vault("renamed");
+            vault.write("keys/old/a", b"a").unwrap();
+            vault.write("keys/old/b", b"b").unwrap();
+            vault.rename("keys/old", "keys/new").unwrap();
+            drop(vault);
+
+            let vault = VaultStorage::open(&path, "vault password").unwrap();
+            assert!(!vault.exists("keys/old"));
+            assert_eq!(vault.read("keys/new/a").unwrap(), b"a");
+            assert_eq!(vault.read("keys/new/b").unwrap(), b"b");
+        }
+
+        #[test]
+        fn a_torn_commit_opens_at_the_last_complete_one() {
+            let (path, vault) = create_vault("torn");
+            vault.write("logs/log", b"committed").unwrap();
+
+            // A change whose records and table are written, cut off halfway
+            // through its commit.
+            {
+                let mut state = vault.state.lock().unwrap();
+                let mut used = used_extents(&state);
+                let sealed = seal(&vault.key, b"lost", &record_ad("logs/log")).unwrap();
+                let record = allocate(&mut used, sealed.len() as u64);
+                write_at(&mut state.file, record.offset, &sealed).unwrap();
+                let mut table = state.table.clone();
+                table.insert(String::from("logs/log"), record);
+
+                let generation = state.generation + 1;
+                let sealed = seal(
+                    &vault.key,
+                    &table_to_bytes(generation, &table),
+                    &table_ad(generation),
+                )
+                .unwrap();
+                let table_extent = allocate(&mut used, sealed.len() as u64);
+                write_at(&mut state.file, table_extent.offset, &sealed).unwrap();
+                let commit = Commit {
+                    generation,
+                    table: table_extent,
+                };
+                let slot = COMMIT_OFFSETS[(generation % 2) as usize];
+                write_at(&mut state.file, slot, &commit.to_bytes()[..20]).unwrap();
+            }
+            drop(vault);
+
+            let vault = VaultStorage::open(&path, "vault password").unwrap();
+            assert_eq!(vault.read("logs/log").unwrap(), b"committed");
+            // The next change goes on from there.
+            vault.write("logs/log", b"again").unwrap();
+            drop(vault);
+            let vault = VaultStorage::open(&path, "vault password").unwrap();
+            assert_eq!(vault.read("logs/log").unwrap(), b"again");
+        }
+    }
+}
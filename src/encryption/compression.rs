@@ -0,0 +1,195 @@
+// Logs are compressed before they are padded and sealed. Whether a log was
+// compressed is a flag in the frame `padding` wraps the plaintext in rather
+// than in the container header: the header is authenticated but readable, and
+// a flag there would tell which logs shrank, which is what padding hides. The
+// frame is opened before anything is decompressed, so the flag is just as
+// binding there.
+//
+// Deflated data starts with the length of what it inflates to, so it can be
+// opened into a buffer of that size: a buffer that grows leaves unwiped copies
+// of the log behind. Logs compressed before the length was recorded are
+// opened into a buffer that is wiped each time it is outgrown.
+//
+// Each journal keeps its setting in the user's profile. Journals that never
+// set one follow the LOG_COMPRESSION env variable, `deflate` (the default) or
+// `none`.
+pub mod compression {
+    use crate::database::profile::profile;
+    use crate::database::storage::storage::Database;
+    use crate::utils::utils::file_system;
+    use flate2::read::DeflateDecoder;
+    use flate2::write::DeflateEncoder;
+    use orion::errors::UnknownCryptoError;
+    use std::convert::TryInto;
+    use std::io::prelude::*;
+    use zeroize::Zeroizing;
+
+    pub const FLAG_DEFLATE: u8 = 1;
+    // Set along with FLAG_DEFLATE when the data starts with its length.
+    pub const FLAG_SIZED: u8 = 2;
+    const SIZED: u8 = FLAG_DEFLATE | FLAG_SIZED;
+    const SIZE_LEN: usize = 4;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Compression {
+        None,
+        Deflate,
+    }
+
+    impl Compression {
+        pub fn from_env() -> Self {
+            match file_system::get_env_var("LOG_COMPRESSION") {
+                Ok(value) if value.trim() == "none" => Compression::None,
+                _ => Compression::Deflate,
+            }
+        }
+
+        pub fn for_user(db: &Database, id: &str) -> Self {
+            match profile::load(db, id) {
+                Ok(profile) => profile.compression.unwrap_or_else(Self::from_env),
+                Err(_) => Self::from_env(),
+            }
+        }
+
+        pub fn parse(value: &str) -> Option<Self> {
+            match value.trim() {
+                "none" => Some(Compression::None),
+                "deflate" => Some(Compression::Deflate),
+                _ => None,
+            }
+        }
+
+        pub fn name(&self) -> &'static str {
+            match self {
+                Compression::None => "none",
+                Compression::Deflate => "deflate",
+            }
+        }
+
+        // Returns the frame flags along with the data to seal. Data that does
+        // not shrink is kept as is.
+        pub fn compress(
+            &self,
+            data: &[u8],
+        ) -> Result<(u8, Zeroizing<Vec<u8>>), UnknownCryptoError> {
+            let uncompressed = || Ok((0, Zeroizing::new(data.to_vec())));
+            if *self == Compression::None || data.len() <= SIZE_LEN {
+                return uncompressed();
+            }
+
+            // Deflated into a buffer the size of the data, which data that
+            // does not shrink overflows.
+            let length: u32 = data.len().try_into().map_err(|_| UnknownCryptoError)?;
+            let mut compressed = Zeroizing::new(vec![0u8; data.len()]);
+            compressed[..SIZE_LEN].copy_from_slice(&length.to_le_bytes());
+            let mut out = &mut compressed[SIZE_LEN..];
+            let mut encoder = DeflateEncoder::new(&mut out, flate2::Compression::best());
+            let fits = encoder
+                .write_all(data)
+                .and_then(|_| encoder.try_finish())
+                .is_ok();
+            drop(encoder);
+            let left = out.len();
+            if !fits || left == 0 {
+                return uncompressed();
+            }
+
+            compressed.truncate(data.len() - left);
+            Ok((FLAG_DEFLATE | FLAG_SIZED, compressed))
+        }
+    }
+
+    pub fn decompress(flags: u8, data: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        let mut decompressed = match flags {
+            0 => return Ok(data.to_vec()),
+            FLAG_DEFLATE => inflate_unsized(data),
+            SIZED => inflate_sized(data),
+            _ => Err(UnknownCryptoError),
+        }?;
+
+        Ok(std::mem::take(&mut *decompressed))
+    }
+
+    fn inflate_sized(data: &[u8]) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let (length, deflated) = match data.get(..SIZE_LEN) {
+            Some(length) => (length.try_into().unwrap(), &data[SIZE_LEN..]),
+            None => return Err(UnknownCryptoError),
+        };
+        let mut decompressed = Zeroizing::new(vec![0u8; u32::from_le_bytes(length) as usize]);
+        let mut decoder = DeflateDecoder::new(deflated);
+        decoder
+            .read_exact(&mut decompressed)
+            .map_err(|_| UnknownCryptoError)?;
+        // Nothing may inflate past the recorded length.
+        match decoder.read(&mut [0u8; 1]) {
+            Ok(0) => Ok(decompressed),
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    fn inflate_unsized(data: &[u8]) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let mut decoder = DeflateDecoder::new(data);
+        let mut decompressed = Zeroizing::new(Vec::with_capacity(data.len().max(64) * 4));
+        loop {
+            if decompressed.len() == decompressed.capacity() {
+                let mut larger = Zeroizing::new(Vec::with_capacity(decompressed.capacity() * 2));
+                larger.extend_from_slice(&decompressed);
+                decompressed = larger;
+            }
+            let filled = decompressed.len();
+            let capacity = decompressed.capacity();
+            decompressed.resize(capacity, 0);
+            match decoder.read(&mut decompressed[filled..]) {
+                Ok(0) => {
+                    decompressed.truncate(filled);
+                    return Ok(decompressed);
+                }
+                Ok(read) => decompressed.truncate(filled + read),
+                Err(_) => return Err(UnknownCryptoError),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn deflated_data_records_its_length() {
+            let data = "a journal entry that repeats itself, ".repeat(200);
+            let (flags, compressed) = Compression::Deflate.compress(data.as_bytes()).unwrap();
+            assert_eq!(flags, FLAG_DEFLATE | FLAG_SIZED);
+            assert_eq!(compressed[..SIZE_LEN], (data.len() as u32).to_le_bytes());
+            assert_eq!(decompress(flags, &compressed).unwrap(), data.as_bytes());
+
+            // A length that does not match what the data inflates to.
+            let mut wrong = compressed.to_vec();
+            wrong[..SIZE_LEN].copy_from_slice(&(data.len() as u32 - 1).to_le_bytes());
+            assert!(decompress(flags, &wrong).is_err());
+            wrong[..SIZE_LEN].copy_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+            assert!(decompress(flags, &wrong).is_err());
+        }
+
+        #[test]
+        fn data_that_does_not_shrink_is_kept() {
+            let data: Vec<u8> = (0..=255u8).collect();
+            let (flags, kept) = Compression::Deflate.compress(&data).unwrap();
+            assert_eq!(flags, 0);
+            assert_eq!(*kept, data);
+            assert_eq!(Compression::None.compress(b"aaaaaaaa").unwrap().0, 0);
+        }
+
+        #[test]
+        fn data_deflated_without_its_length_still_opens() {
+            let data = "x".repeat(100_000);
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(data.as_bytes()).unwrap();
+            let deflated = encoder.finish().unwrap();
+
+            assert_eq!(
+                decompress(FLAG_DEFLATE, &deflated).unwrap(),
+                data.as_bytes()
+            );
+        }
+    }
+}
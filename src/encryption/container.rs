@@ -0,0 +1,259 @@
+// On-disk layout of an encrypted log (all integers little endian):
+//
+//   magic "CEJL" | version u8 | cipher u8 | kdf u8 | iterations u32 | memory u32
+//   | key ref u8 | nonce [u8; 24] | ciphertext + poly1305 tag
+//
+// The key ref tells which key sealed the body: the password-derived key (from
+// the user's salt and the KDF parameters in the header) or the user's master
+// key, which is itself stored sealed under the password-derived key and,
+// optionally, under the user's recovery key.
+//
+// The header bytes are authenticated as associated data. From version 2 on the
+// associated data also carries the identity of the sealed object (user, date
+// and log id), so a file that is copied to another user, day or position no
+// longer opens. From version 3 on the plaintext is framed and padded, see
+// `padding`. Large logs use the streaming cipher and are laid out in chunks
+// after the header, see `stream`. Files written before the header existed
+// carry no magic and are read as version 0, i.e. the raw output of
+// `orion::aead::seal` under the default Argon2 parameters.
+pub mod container {
+    use orion::errors::UnknownCryptoError;
+    use orion::util::secure_rand_bytes;
+    use std::convert::TryInto;
+
+    pub const MAGIC: [u8; 4] = *b"CEJL";
+    pub const CURRENT_VERSION: u8 = 3;
+    pub const NONCE_SIZE: usize = 24;
+    pub const HEADER_SIZE: usize = 40;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CipherSuite {
+        XChaCha20Poly1305 = 1,
+        XChaCha20Poly1305Stream = 2,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum KdfAlgorithm {
+        None = 0,
+        Argon2i = 1,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum KeyRef {
+        Password = 1,
+        MasterKey = 2,
+        Recovery = 3,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct KdfParams {
+        pub algorithm: KdfAlgorithm,
+        pub iterations: u32,
+        pub memory: u32,
+    }
+
+    impl KdfParams {
+        pub fn none() -> Self {
+            Self {
+                algorithm: KdfAlgorithm::None,
+                iterations: 0,
+                memory: 0,
+            }
+        }
+    }
+
+    impl Default for KdfParams {
+        fn default() -> Self {
+            Self {
+                algorithm: KdfAlgorithm::Argon2i,
+                iterations: 3,
+                memory: 1 << 16,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Header {
+        pub version: u8,
+        pub cipher: CipherSuite,
+        pub kdf: KdfParams,
+        pub key: KeyRef,
+        pub nonce: [u8; NONCE_SIZE],
+    }
+
+    #[derive(Debug)]
+    pub enum Container<'a> {
+        V0(&'a [u8]),
+        V1(Header, &'a [u8]),
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct LogIdentity<'a> {
+        pub user: &'a str,
+        pub date: &'a str,
+        pub log_id: &'a str,
+    }
+
+    impl<'a> LogIdentity<'a> {
+        pub fn master_key(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "master.key",
+            }
+        }
+
+        pub fn recovery_key(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "recovery.key",
+            }
+        }
+
+        pub fn index(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "index",
+            }
+        }
+
+        pub fn manifest(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "manifest",
+            }
+        }
+
+        pub fn checkpoint(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "manifest.head",
+            }
+        }
+
+        pub fn signing_key(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "signing.key",
+            }
+        }
+
+        pub fn journal(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "journal",
+            }
+        }
+
+        pub fn duress_slots(user: &'a str) -> Self {
+            Self {
+                user,
+                date: "",
+                log_id: "duress.slots",
+            }
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            for field in [self.user, self.date, self.log_id].iter() {
+                bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(field.as_bytes());
+            }
+            bytes
+        }
+    }
+
+    impl Header {
+        pub fn new(kdf: KdfParams, key: KeyRef) -> Result<Self, UnknownCryptoError> {
+            let mut nonce = [0u8; NONCE_SIZE];
+            secure_rand_bytes(&mut nonce)?;
+
+            Ok(Self {
+                version: CURRENT_VERSION,
+                cipher: CipherSuite::XChaCha20Poly1305,
+                kdf,
+                key,
+                nonce,
+            })
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::with_capacity(HEADER_SIZE);
+            bytes.extend_from_slice(&MAGIC);
+            bytes.push(self.version);
+            bytes.push(self.cipher as u8);
+            bytes.push(self.kdf.algorithm as u8);
+            bytes.extend_from_slice(&self.kdf.iterations.to_le_bytes());
+            bytes.extend_from_slice(&self.kdf.memory.to_le_bytes());
+            bytes.push(self.key as u8);
+            bytes.extend_from_slice(&self.nonce);
+            bytes
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> Result<Self, UnknownCryptoError> {
+            if bytes.len() < HEADER_SIZE || bytes[0..4] != MAGIC {
+                return Err(UnknownCryptoError);
+            }
+
+            let version = bytes[4];
+            if version == 0 || version > CURRENT_VERSION {
+                return Err(UnknownCryptoError);
+            }
+            let cipher = match bytes[5] {
+                1 => CipherSuite::XChaCha20Poly1305,
+                2 => CipherSuite::XChaCha20Poly1305Stream,
+                _ => return Err(UnknownCryptoError),
+            };
+            let algorithm = match bytes[6] {
+                0 => KdfAlgorithm::None,
+                1 => KdfAlgorithm::Argon2i,
+                _ => return Err(UnknownCryptoError),
+            };
+            let iterations = u32::from_le_bytes(bytes[7..11].try_into().unwrap());
+            let memory = u32::from_le_bytes(bytes[11..15].try_into().unwrap());
+            let key = match bytes[15] {
+                1 => KeyRef::Password,
+                2 => KeyRef::MasterKey,
+                3 => KeyRef::Recovery,
+                _ => return Err(UnknownCryptoError),
+            };
+            let mut nonce = [0u8; NONCE_SIZE];
+            nonce.copy_from_slice(&bytes[16..HEADER_SIZE]);
+
+            Ok(Self {
+                version,
+                cipher,
+                kdf: KdfParams {
+                    algorithm,
+                    iterations,
+                    memory,
+                },
+                key,
+                nonce,
+            })
+        }
+
+        pub fn associated_data(&self, identity: &LogIdentity) -> Vec<u8> {
+            let mut associated_data = self.to_bytes();
+            if self.version >= 2 {
+                associated_data.extend_from_slice(&identity.to_bytes());
+            }
+            associated_data
+        }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Container<'_>, UnknownCryptoError> {
+        if !data.starts_with(&MAGIC) {
+            return Ok(Container::V0(data));
+        }
+
+        let header = Header::from_bytes(data)?;
+        Ok(Container::V1(header, &data[HEADER_SIZE..]))
+    }
+}
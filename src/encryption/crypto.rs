@@ -1,38 +1,387 @@
 pub mod crypto {
     use crate::database::keychain::key_chain;
+    use crate::database::storage::storage::Database;
+    use crate::encryption::compression::compression::{self, Compression};
+    use crate::encryption::container::container::{
+        self, CipherSuite, Container, Header, KdfParams, KeyRef, LogIdentity, CURRENT_VERSION,
+    };
+    use crate::encryption::padding::padding::{self, Padding};
+    use crate::encryption::session::session::Session;
+    use crate::encryption::slots::slots::SALT_SIZE;
+    use crate::utils::utils::file_system;
     use orion::aead;
+    use orion::errors::UnknownCryptoError;
+    use orion::hazardous::aead::xchacha20poly1305;
+    use orion::hazardous::hash::blake2b::{self, Blake2b};
+    use orion::hazardous::kdf::hkdf;
+    use orion::hazardous::mac::poly1305::POLY1305_OUTSIZE;
     use orion::kdf;
+    use zeroize::Zeroizing;
+
     pub fn encrypt_str(
-        user: &str,
+        session: &Session,
+        identity: &LogIdentity,
         message: &str,
-        password: &str,
-    ) -> Result<Vec<u8>, orion::errors::UnknownCryptoError> {
-        let pass_salt = recover_pass_salt(user, password)?;
-        let derived_key = kdf::derive_key(&pass_salt.0, &pass_salt.1, 3, 1 << 16, 32)?;
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        encrypt_with_master_key(
+            session.db(),
+            session.master_key(),
+            identity,
+            message.as_bytes(),
+        )
+    }
+
+    // Compressed as the profile of `identity.user` in `db` says.
+    pub fn encrypt_with_master_key(
+        db: &Database,
+        master_key: &kdf::SecretKey,
+        identity: &LogIdentity,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let header = Header::new(KdfParams::none(), KeyRef::MasterKey)?;
 
-        aead::seal(&derived_key, message.as_bytes())
+        seal(
+            master_key,
+            &header,
+            identity,
+            plaintext,
+            &Compression::for_user(db, identity.user),
+            &Padding::from_env(),
+        )
+    }
+
+    pub fn needs_rekey(ciphertext: &[u8]) -> Result<bool, UnknownCryptoError> {
+        match container::parse(ciphertext)? {
+            Container::V0(_) => Ok(true),
+            Container::V1(header, _) => {
+                Ok(header.key == KeyRef::Password || header.version < CURRENT_VERSION)
+            }
+        }
     }
 
     pub fn decrypt_str(
+        session: &Session,
+        identity: &LogIdentity,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        match container::parse(ciphertext)? {
+            Container::V0(body) => aead::open(session.password_key(&KdfParams::default())?, body),
+            Container::V1(header, body) => {
+                let secret_key = match header.key {
+                    KeyRef::Password => session.password_key(&header.kdf)?,
+                    KeyRef::MasterKey => session.master_key(),
+                    KeyRef::Recovery => return Err(UnknownCryptoError),
+                };
+                open(secret_key, &header, identity, body)
+            }
+        }
+    }
+
+    pub fn generate_master_key() -> kdf::SecretKey {
+        kdf::SecretKey::default()
+    }
+
+    pub fn wrap_master_key(
+        db: &Database,
+        user: &str,
+        password: &str,
+        master_key: &kdf::SecretKey,
+        params: &KdfParams,
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let salt = key_chain::get_key_salt(db, user).map_err(|_| UnknownCryptoError)?;
+        wrap_master_key_with_salt(user, password, &salt, master_key, params)
+    }
+
+    pub fn wrap_master_key_with_salt(
+        user: &str,
+        password: &str,
+        salt: &[u8],
+        master_key: &kdf::SecretKey,
+        params: &KdfParams,
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let derived_key = derive_password_key(password, salt, params)?;
+        let header = Header::new(*params, KeyRef::Password)?;
+
+        seal(
+            &derived_key,
+            &header,
+            &LogIdentity::master_key(user),
+            master_key.unprotected_as_bytes(),
+            &Compression::None,
+            &Padding::None,
+        )
+    }
+
+    pub fn get_master_key_params(wrapped: &[u8]) -> Result<KdfParams, UnknownCryptoError> {
+        match container::parse(wrapped)? {
+            Container::V1(header, _) if header.key == KeyRef::Password => Ok(header.kdf),
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    pub fn unwrap_master_key(
+        user: &str,
+        derived_key: &kdf::SecretKey,
+        wrapped: &[u8],
+    ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+        match container::parse(wrapped)? {
+            Container::V1(header, body) if header.key == KeyRef::Password => {
+                let identity = LogIdentity::master_key(user);
+                kdf::SecretKey::from_slice(&open(derived_key, &header, &identity, body)?)
+            }
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    pub fn wrap_master_key_for_recovery(
+        user: &str,
+        recovery_key: &kdf::SecretKey,
+        master_key: &kdf::SecretKey,
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let header = Header::new(KdfParams::none(), KeyRef::Recovery)?;
+
+        seal(
+            recovery_key,
+            &header,
+            &LogIdentity::recovery_key(user),
+            master_key.unprotected_as_bytes(),
+            &Compression::None,
+            &Padding::None,
+        )
+    }
+
+    pub fn unwrap_master_key_with_recovery(
+        user: &str,
+        recovery_key: &kdf::SecretKey,
+        wrapped: &[u8],
+    ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+        match container::parse(wrapped)? {
+            Container::V1(header, body) if header.key == KeyRef::Recovery => {
+                let identity = LogIdentity::recovery_key(user);
+                kdf::SecretKey::from_slice(&open(recovery_key, &header, &identity, body)?)
+            }
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    pub fn derive_key(
+        db: &Database,
         user: &str,
-        ciphertext: &str,
         password: &str,
-    ) -> Result<Vec<u8>, orion::errors::UnknownCryptoError> {
-        let pass_salt = recover_pass_salt(user, password)?;
-        let secret_key = kdf::derive_key(&pass_salt.0, &pass_salt.1, 3, 1 << 16, 32)?;
-        aead::open(&secret_key, &ciphertext.as_bytes())
+        params: &KdfParams,
+    ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+        let pass_salt = recover_pass_salt(db, user, password)?;
+        kdf::derive_key(
+            &pass_salt.0,
+            &pass_salt.1,
+            params.iterations,
+            params.memory,
+            32,
+        )
+    }
+
+    pub fn derive_password_key(
+        password: &str,
+        salt: &[u8],
+        params: &KdfParams,
+    ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+        let pass = kdf::Password::from_slice(password.as_bytes())?;
+        let salt = kdf::Salt::from_slice(salt)?;
+        kdf::derive_key(&pass, &salt, params.iterations, params.memory, 32)
+    }
+
+    // A log sealed with a password of its own is wrapped once more before it
+    // is sealed with the master key like any other log:
+    //
+    //   salt [u8; 16] | header (key ref = Password) | ciphertext
+    //
+    // The outer layer pads it, so the inner one is only compressed.
+    pub fn seal_with_password(
+        db: &Database,
+        identity: &LogIdentity,
+        key: &LogPasswordKey,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let header = Header::new(key.params, KeyRef::Password)?;
+
+        let mut sealed = key.salt.clone();
+        sealed.extend_from_slice(&seal(
+            &key.key,
+            &header,
+            identity,
+            plaintext,
+            &Compression::for_user(db, identity.user),
+            &Padding::None,
+        )?);
+        Ok(sealed)
+    }
+
+    pub fn open_with_password(
+        identity: &LogIdentity,
+        key: &LogPasswordKey,
+        sealed: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let (salt, header, body) = parse_password_sealed(sealed)?;
+        if salt != key.salt.as_slice() || header.kdf != key.params {
+            return Err(UnknownCryptoError);
+        }
+
+        open(&key.key, &header, identity, body).map(Zeroizing::new)
+    }
+
+    // The key a log's own password derives, kept with the salt it was derived
+    // with so that the log can be sealed again without another derivation.
+    pub struct LogPasswordKey {
+        salt: Vec<u8>,
+        params: KdfParams,
+        key: kdf::SecretKey,
+    }
+
+    impl LogPasswordKey {
+        // A key under a new salt, to seal a log with.
+        pub fn new(password: &str, params: &KdfParams) -> Result<Self, UnknownCryptoError> {
+            let salt = kdf::Salt::default();
+            Ok(Self {
+                key: derive_password_key(password, salt.as_ref(), params)?,
+                salt: salt.as_ref().to_vec(),
+                params: *params,
+            })
+        }
+
+        // The key that opens `sealed`, if `password` is right.
+        pub fn for_sealed(password: &str, sealed: &[u8]) -> Result<Self, UnknownCryptoError> {
+            let (salt, header, _) = parse_password_sealed(sealed)?;
+            Ok(Self {
+                key: derive_password_key(password, salt, &header.kdf)?,
+                salt: salt.to_vec(),
+                params: header.kdf,
+            })
+        }
+    }
+
+    fn parse_password_sealed(sealed: &[u8]) -> Result<(&[u8], Header, &[u8]), UnknownCryptoError> {
+        if sealed.len() < SALT_SIZE {
+            return Err(UnknownCryptoError);
+        }
+        let (salt, container) = sealed.split_at(SALT_SIZE);
+        match container::parse(container)? {
+            Container::V1(header, body) if header.key == KeyRef::Password => {
+                Ok((salt, header, body))
+            }
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    fn seal(
+        secret_key: &kdf::SecretKey,
+        header: &Header,
+        identity: &LogIdentity,
+        plaintext: &[u8],
+        compression: &Compression,
+        padding: &Padding,
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        let (flags, compressed) = compression.compress(plaintext)?;
+        let plaintext = Zeroizing::new(padding::pad(flags, &compressed, padding)?);
+        let header_bytes = header.to_bytes();
+        let mut sealed = vec![0u8; header_bytes.len() + plaintext.len() + POLY1305_OUTSIZE];
+        sealed[..header_bytes.len()].copy_from_slice(&header_bytes);
+
+        xchacha20poly1305::seal(
+            &xchacha20poly1305::SecretKey::from_slice(secret_key.unprotected_as_bytes())?,
+            &xchacha20poly1305::Nonce::from_slice(&header.nonce)?,
+            &plaintext,
+            Some(&header.associated_data(identity)),
+            &mut sealed[header_bytes.len()..],
+        )?;
+
+        Ok(sealed)
+    }
+
+    fn open(
+        secret_key: &kdf::SecretKey,
+        header: &Header,
+        identity: &LogIdentity,
+        body: &[u8],
+    ) -> Result<Vec<u8>, UnknownCryptoError> {
+        if header.cipher != CipherSuite::XChaCha20Poly1305 || body.len() < POLY1305_OUTSIZE {
+            return Err(UnknownCryptoError);
+        }
+
+        let mut plaintext = Zeroizing::new(vec![0u8; body.len() - POLY1305_OUTSIZE]);
+        xchacha20poly1305::open(
+            &xchacha20poly1305::SecretKey::from_slice(secret_key.unprotected_as_bytes())?,
+            &xchacha20poly1305::Nonce::from_slice(&header.nonce)?,
+            body,
+            Some(&header.associated_data(identity)),
+            &mut plaintext,
+        )?;
+
+        if header.version < 3 {
+            return Ok(std::mem::take(&mut *plaintext));
+        }
+        let (flags, data) = padding::unpad(&plaintext)?;
+        compression::decompress(flags, data)
+    }
+
+    // A keyfile is folded into the secret both the password hash and the key
+    // derivation are computed from, so neither opens without it.
+    pub fn mix_keyfile(
+        password: &str,
+        keyfile: &[u8],
+    ) -> Result<Zeroizing<String>, UnknownCryptoError> {
+        let mut state = Blake2b::new(None, 32)?;
+        state.update(keyfile)?;
+        let digest = state.finalize()?;
+
+        let mut secret = Zeroizing::new(String::with_capacity(password.len() + 65));
+        secret.push_str(password);
+        secret.push('\u{0}');
+        for byte in digest.as_ref() {
+            secret.push_str(&format!("{:02x}", byte));
+        }
+        Ok(secret)
+    }
+
+    // The master key only seals; every other use gets a key of its own,
+    // derived from it under a label naming that use.
+    pub fn derive_subkey(
+        master_key: &kdf::SecretKey,
+        label: &str,
+    ) -> Result<Zeroizing<[u8; 32]>, UnknownCryptoError> {
+        let mut subkey = Zeroizing::new([0u8; 32]);
+        hkdf::sha512::derive_key(
+            b"crypto_editor subkey",
+            master_key.unprotected_as_bytes(),
+            Some(label.as_bytes()),
+            &mut *subkey,
+        )?;
+
+        Ok(subkey)
+    }
+
+    // A name that looks like any other blob name but can only be derived with
+    // the master key.
+    pub fn derive_blob_name(
+        master_key: &kdf::SecretKey,
+        purpose: &str,
+    ) -> Result<String, UnknownCryptoError> {
+        let key = blake2b::SecretKey::from_slice(&*derive_subkey(master_key, "blob name")?)?;
+        let mut state = Blake2b::new(Some(&key), 16)?;
+        state.update(purpose.as_bytes())?;
+
+        Ok(file_system::to_hex(state.finalize()?.as_ref()))
     }
 
     pub fn recover_pass_salt(
+        db: &Database,
         user: &str,
         password: &str,
-    ) -> Result<(kdf::Password, kdf::Salt), orion::errors::UnknownCryptoError> {
+    ) -> Result<(kdf::Password, kdf::Salt), UnknownCryptoError> {
         let pass = kdf::Password::from_slice(password.as_bytes())?;
-        let mut salt = kdf::Salt::default();
-        match key_chain::get_key_salt(user) {
-            Ok(salt_str) => Ok(salt = kdf::Salt::from_slice(&salt_str)?),
-            Err(_) => Err(orion::errors::UnknownCryptoError),
-        }?;
+        let salt = match key_chain::get_key_salt(db, user) {
+            Ok(salt_str) => kdf::Salt::from_slice(&salt_str)?,
+            Err(_) => return Err(UnknownCryptoError),
+        };
 
         Ok((pass, salt))
     }
@@ -0,0 +1,81 @@
+pub mod kdf_policy {
+    use crate::encryption::container::container::{KdfAlgorithm, KdfParams};
+    use crate::utils::utils::file_system;
+    use orion::errors::UnknownCryptoError;
+    use orion::kdf;
+    use std::time::{Duration, Instant};
+
+    const DEFAULT_MIN_ITERATIONS: u32 = 3;
+    const DEFAULT_MIN_MEMORY: u32 = 1 << 16;
+    const DEFAULT_TARGET_MS: u32 = 500;
+    // 256 MiB. The process locks its memory, so a derivation must fit in RAM
+    // next to everything else, including on the machine a vault is moved to.
+    const MAX_MEMORY: u32 = 1 << 18;
+    const MAX_ITERATIONS: u32 = 64;
+
+    // The minimum Argon2 cost every user must meet and the unlock time new
+    // parameters are calibrated to. Memory is in KiB, as Argon2 expects.
+    #[derive(Debug, Clone, Copy)]
+    pub struct KdfPolicy {
+        pub min_iterations: u32,
+        pub min_memory: u32,
+        pub target: Duration,
+    }
+
+    impl KdfPolicy {
+        pub fn from_env() -> Self {
+            Self {
+                min_iterations: read_env_u32("KDF_MIN_ITERATIONS", DEFAULT_MIN_ITERATIONS).max(3),
+                min_memory: read_env_u32("KDF_MIN_MEMORY", DEFAULT_MIN_MEMORY).max(8),
+                target: Duration::from_millis(u64::from(read_env_u32(
+                    "KDF_TARGET_MS",
+                    DEFAULT_TARGET_MS,
+                ))),
+            }
+        }
+
+        pub fn is_satisfied_by(&self, params: &KdfParams) -> bool {
+            params.algorithm == KdfAlgorithm::Argon2i
+                && params.iterations >= self.min_iterations
+                && params.memory >= self.min_memory
+        }
+
+        // Starts at the policy minimum and doubles memory, then iterations,
+        // until one derivation on this machine takes at least the target time.
+        pub fn calibrate(&self) -> Result<KdfParams, UnknownCryptoError> {
+            let mut params = KdfParams {
+                algorithm: KdfAlgorithm::Argon2i,
+                iterations: self.min_iterations,
+                memory: self.min_memory,
+            };
+
+            while measure(&params)? < self.target {
+                if params.memory < MAX_MEMORY {
+                    params.memory = (params.memory * 2).min(MAX_MEMORY);
+                } else if params.iterations < MAX_ITERATIONS {
+                    params.iterations += 1;
+                } else {
+                    break;
+                }
+            }
+
+            Ok(params)
+        }
+    }
+
+    fn measure(params: &KdfParams) -> Result<Duration, UnknownCryptoError> {
+        let password = kdf::Password::from_slice(b"calibration")?;
+        let salt = kdf::Salt::default();
+
+        let start = Instant::now();
+        kdf::derive_key(&password, &salt, params.iterations, params.memory, 32)?;
+        Ok(start.elapsed())
+    }
+
+    fn read_env_u32(var_name: &str, default: u32) -> u32 {
+        match file_system::get_env_var(var_name) {
+            Ok(value) => value.parse::<u32>().unwrap_or(default),
+            Err(_) => default,
+        }
+    }
+}
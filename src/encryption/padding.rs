@@ -0,0 +1,159 @@
+// From container version 3 on the sealed plaintext is framed as
+//
+//   flags u8 | length u32 | data | zero padding
+//
+// so the size of a file no longer gives away the length of what was written.
+// The padding scheme is picked with the LOG_PADDING env variable: `padme`
+// (the default), `none` or `bucket:<bytes>`. Readers only need the length in
+// the frame, so logs written under different schemes open alike.
+pub mod padding {
+    use crate::utils::utils::file_system;
+    use orion::errors::UnknownCryptoError;
+    use std::convert::TryInto;
+
+    pub const FRAME_SIZE: usize = 5;
+    const DEFAULT_BUCKET: usize = 4096;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Padding {
+        None,
+        // Pads to a length whose low bits are zero, with at most 12% overhead
+        // and O(log log n) bits of the length left.
+        Padme,
+        Bucket(usize),
+    }
+
+    impl Padding {
+        pub fn from_env() -> Self {
+            match file_system::get_env_var("LOG_PADDING") {
+                Ok(value) => Padding::parse(&value).unwrap_or(Padding::Padme),
+                Err(_) => Padding::Padme,
+            }
+        }
+
+        pub fn parse(value: &str) -> Option<Self> {
+            match value.trim() {
+                "none" => Some(Padding::None),
+                "padme" => Some(Padding::Padme),
+                "bucket" => Some(Padding::Bucket(DEFAULT_BUCKET)),
+                other => {
+                    let size = other.strip_prefix("bucket:")?.parse::<usize>().ok()?;
+                    if size == 0 {
+                        return None;
+                    }
+                    Some(Padding::Bucket(size))
+                }
+            }
+        }
+
+        pub fn padded_len(&self, len: usize) -> usize {
+            match self {
+                Padding::None => len,
+                Padding::Padme => padme(len),
+                Padding::Bucket(size) => len.div_ceil(*size).max(1) * size,
+            }
+        }
+    }
+
+    pub fn pad(flags: u8, data: &[u8], padding: &Padding) -> Result<Vec<u8>, UnknownCryptoError> {
+        let length: u32 = data.len().try_into().map_err(|_| UnknownCryptoError)?;
+        let mut framed = Vec::with_capacity(padding.padded_len(FRAME_SIZE + data.len()));
+        framed.push(flags);
+        framed.extend_from_slice(&length.to_le_bytes());
+        framed.extend_from_slice(data);
+        framed.resize(padding.padded_len(framed.len()), 0);
+
+        Ok(framed)
+    }
+
+    pub fn unpad(framed: &[u8]) -> Result<(u8, &[u8]), UnknownCryptoError> {
+        if framed.len() < FRAME_SIZE {
+            return Err(UnknownCryptoError);
+        }
+
+        let length = u32::from_le_bytes(framed[1..FRAME_SIZE].try_into().unwrap()) as usize;
+        match framed[FRAME_SIZE..].get(..length) {
+            Some(data) => Ok((framed[0], data)),
+            None => Err(UnknownCryptoError),
+        }
+    }
+
+    fn padme(len: usize) -> usize {
+        if len < 2 {
+            return len;
+        }
+
+        let exponent = usize::BITS - 1 - len.leading_zeros();
+        let exponent_bits = u32::BITS - exponent.leading_zeros();
+        let mask = (1usize << (exponent - exponent_bits)) - 1;
+        (len + mask) & !mask
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn padme_rounds_up_by_at_most_twelve_percent() {
+            for len in 0..100_000 {
+                let padded = padme(len);
+                assert!(padded >= len);
+                assert!(padded - len <= len / 8 + 1, "{} -> {}", len, padded);
+            }
+            assert_eq!(padme(1000), 1024);
+            assert_eq!(padme(100_000), 100_352);
+        }
+
+        #[test]
+        fn padme_leaves_few_distinct_sizes() {
+            // Lengths from 4096 on are rounded to multiples of 256.
+            let mut sizes: Vec<usize> = (4096..8192).map(padme).collect();
+            sizes.dedup();
+            assert_eq!(sizes.len(), 17);
+            assert!(sizes.iter().all(|size| size % 256 == 0));
+        }
+
+        #[test]
+        fn buckets_round_up_to_whole_buckets() {
+            let padding = Padding::Bucket(100);
+            assert_eq!(padding.padded_len(0), 100);
+            assert_eq!(padding.padded_len(100), 100);
+            assert_eq!(padding.padded_len(101), 200);
+            assert_eq!(Padding::None.padded_len(101), 101);
+        }
+
+        #[test]
+        fn pad_and_unpad_round_trip() {
+            for padding in [Padding::None, Padding::Padme, Padding::Bucket(64)] {
+                for len in [0, 1, 5, 63, 64, 1000, 5000] {
+                    let data = vec![7u8; len];
+                    let framed = pad(3, &data, &padding).unwrap();
+                    assert_eq!(framed.len(), padding.padded_len(FRAME_SIZE + len));
+                    assert_eq!(unpad(&framed).unwrap(), (3, &data[..]));
+                }
+            }
+        }
+
+        #[test]
+        fn unpad_rejects_bad_frames() {
+            assert!(unpad(&[0, 1, 0]).is_err());
+            let mut framed = pad(0, b"hello", &Padding::None).unwrap();
+            framed[1] = 6;
+            assert!(unpad(&framed).is_err());
+        }
+
+        #[test]
+        fn parses_padding_settings() {
+            assert_eq!(Padding::parse("none"), Some(Padding::None));
+            assert_eq!(Padding::parse(" padme "), Some(Padding::Padme));
+            assert_eq!(
+                Padding::parse("bucket"),
+                Some(Padding::Bucket(DEFAULT_BUCKET))
+            );
+            assert_eq!(Padding::parse("bucket:512"), Some(Padding::Bucket(512)));
+            assert_eq!(Padding::parse("bucket:0"), None);
+            assert_eq!(Padding::parse("bucket:x"), None);
+            assert_eq!(Padding::parse("zeros"), None);
+        }
+    }
+}
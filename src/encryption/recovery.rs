@@ -0,0 +1,91 @@
+// A recovery key is 32 random bytes that unlock the master key without the
+// password. It is shown to the user once as a phrase of 33 words: one word per
+// byte followed by a checksum word, so a mistyped phrase is rejected before it
+// is tried against the stored key.
+pub mod recovery {
+    use orion::errors::UnknownCryptoError;
+    use orion::hazardous::hash::blake2b::Blake2b;
+    use orion::kdf;
+    use zeroize::Zeroizing;
+
+    pub const KEY_SIZE: usize = 32;
+
+    const WORDS: [&str; 256] = [
+        "able", "acid", "aged", "also", "area", "army", "away", "baby", "back", "ball", "band",
+        "bank", "base", "bath", "bear", "beat", "bell", "belt", "bend", "best", "bird", "blue",
+        "boat", "body", "bold", "bone", "book", "boot", "born", "boss", "both", "bowl", "bulk",
+        "burn", "bush", "busy", "cafe", "cake", "calm", "camp", "card", "care", "cart", "case",
+        "cash", "cast", "cell", "chef", "chin", "city", "clay", "clip", "club", "coal", "coat",
+        "code", "coin", "cold", "cook", "cool", "copy", "cord", "core", "corn", "cost", "crew",
+        "crop", "cube", "cute", "dark", "data", "dawn", "deal", "dear", "deep", "deer", "desk",
+        "dial", "dice", "diet", "dish", "dock", "door", "dose", "dove", "down", "draw", "drum",
+        "duck", "dune", "dust", "duty", "earn", "east", "easy", "echo", "edge", "epic", "even",
+        "exit", "face", "fact", "fair", "fall", "farm", "fast", "fern", "film", "find", "fire",
+        "fish", "five", "flag", "flat", "flow", "foam", "fold", "folk", "food", "foot", "fork",
+        "form", "fort", "four", "frog", "fuel", "full", "fund", "gain", "game", "gate", "gear",
+        "gift", "girl", "glad", "glow", "glue", "goat", "gold", "golf", "good", "gown", "grab",
+        "gray", "grid", "grin", "grow", "gulf", "hair", "half", "hall", "hand", "harp", "hawk",
+        "head", "heat", "herb", "hero", "hill", "hint", "hive", "hold", "home", "hook", "hope",
+        "horn", "host", "hour", "huge", "hunt", "idea", "inch", "iron", "item", "jazz", "join",
+        "joke", "jump", "jury", "keen", "kept", "kick", "kind", "king", "kite", "knee", "knot",
+        "lace", "lake", "lamp", "land", "lane", "last", "lawn", "leaf", "lens", "lift", "lily",
+        "lime", "line", "lion", "list", "loaf", "lock", "loft", "long", "loop", "lord", "loud",
+        "luck", "lung", "made", "mail", "main", "mall", "mask", "meal", "melt", "menu", "mild",
+        "milk", "mind", "mint", "mist", "mode", "moon", "moss", "moth", "move", "much", "mule",
+        "nail", "name", "navy", "neck", "nest", "news", "nice", "nine", "node", "noon", "nose",
+        "note", "oboe", "open", "oval", "oven", "pace", "page", "palm", "park", "path", "peak",
+        "pear", "pine", "pink",
+    ];
+
+    pub fn generate_recovery_key() -> kdf::SecretKey {
+        kdf::SecretKey::default()
+    }
+
+    pub fn to_mnemonic(key: &kdf::SecretKey) -> Result<Zeroizing<String>, UnknownCryptoError> {
+        encode_words(key.unprotected_as_bytes())
+    }
+
+    pub fn from_mnemonic(phrase: &str) -> Result<kdf::SecretKey, UnknownCryptoError> {
+        let bytes = decode_words(phrase)?;
+        if bytes.len() != KEY_SIZE {
+            return Err(UnknownCryptoError);
+        }
+
+        kdf::SecretKey::from_slice(&bytes)
+    }
+
+    // One word per byte followed by a checksum word. Also used for the shares
+    // a recovery key is split into.
+    pub fn encode_words(bytes: &[u8]) -> Result<Zeroizing<String>, UnknownCryptoError> {
+        let mut words: Vec<&str> = bytes.iter().map(|byte| WORDS[*byte as usize]).collect();
+        words.push(WORDS[checksum(bytes)? as usize]);
+
+        Ok(Zeroizing::new(words.join(" ")))
+    }
+
+    // The words are the secret, so they are matched in place rather than
+    // copied, and the bytes are collected into a buffer that never grows.
+    pub fn decode_words(phrase: &str) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(phrase.split_whitespace().count()));
+        for word in phrase.split_whitespace() {
+            match WORDS
+                .iter()
+                .position(|candidate| candidate.eq_ignore_ascii_case(word))
+            {
+                Some(idx) => bytes.push(idx as u8),
+                None => return Err(UnknownCryptoError),
+            }
+        }
+
+        match bytes.pop() {
+            Some(sum) if !bytes.is_empty() && checksum(&bytes)? == sum => Ok(bytes),
+            _ => Err(UnknownCryptoError),
+        }
+    }
+
+    fn checksum(bytes: &[u8]) -> Result<u8, UnknownCryptoError> {
+        let mut state = Blake2b::new(None, 32)?;
+        state.update(bytes)?;
+        Ok(state.finalize()?.as_ref()[0])
+    }
+}
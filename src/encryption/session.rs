@@ -0,0 +1,161 @@
+pub mod session {
+    use crate::database::keychain::key_chain;
+    use crate::database::storage::storage::Database;
+    use crate::encryption::container::container::{KdfParams, LogIdentity};
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::slots::slots::SlotKind;
+    use crate::utils::utils::file_system;
+    use orion::errors::UnknownCryptoError;
+    use orion::kdf;
+    use std::io::Error;
+
+    // An unlocked journal of `db`. The Argon2 derivation runs once in `unlock`; the
+    // resulting keys are wiped from memory when the session is dropped. Only
+    // a session opened from the legacy key files keeps the password-derived
+    // key, which older logs may still be sealed with.
+    //
+    // A duress slot opens a decoy journal, which keeps the files that are
+    // found by a fixed name in a real journal among the blobs instead.
+    pub struct Session {
+        db: Database,
+        user: String,
+        master_key: kdf::SecretKey,
+        slot: Option<usize>,
+        decoy: bool,
+        password_key: Option<kdf::SecretKey>,
+        password_params: KdfParams,
+    }
+
+    impl Session {
+        // Tries every password slot in turn, so the cost of a wrong password
+        // grows with the number of slots.
+        pub fn unlock(db: &Database, user: &str, secret: &str) -> Result<Self, UnknownCryptoError> {
+            let slots = key_chain::get_slots(db, user).map_err(|_| UnknownCryptoError)?;
+            for (pos, slot) in slots.iter() {
+                if slot.kind == SlotKind::Recovery {
+                    continue;
+                }
+                if let Ok(master_key) = slot.open_with_secret(user, secret) {
+                    let mut session = Self::with_master_key(db, user, master_key, Some(*pos));
+                    if let Some(wipe) = key_chain::open_decoy(&session) {
+                        session.decoy = true;
+                        if wipe {
+                            let _ = key_chain::wipe_other_slots(db, user, *pos);
+                        }
+                    }
+                    return Ok(session);
+                }
+            }
+
+            Err(UnknownCryptoError)
+        }
+
+        pub fn recover(
+            db: &Database,
+            user: &str,
+            recovery_key: &kdf::SecretKey,
+        ) -> Result<Self, UnknownCryptoError> {
+            let slots = key_chain::get_slots(db, user).map_err(|_| UnknownCryptoError)?;
+            for (pos, slot) in slots.iter() {
+                if let Ok(master_key) = slot.open_with_recovery(user, recovery_key) {
+                    return Ok(Self::with_master_key(db, user, master_key, Some(*pos)));
+                }
+            }
+
+            Err(UnknownCryptoError)
+        }
+
+        pub fn unlock_legacy(
+            db: &Database,
+            user: &str,
+            password: &str,
+        ) -> Result<Self, UnknownCryptoError> {
+            let wrapped = key_chain::get_master_key(db, user).map_err(|_| UnknownCryptoError)?;
+            let password_params = crypto::get_master_key_params(&wrapped)?;
+            let password_key = crypto::derive_key(db, user, password, &password_params)?;
+            let master_key = crypto::unwrap_master_key(user, &password_key, &wrapped)?;
+
+            Ok(Self {
+                db: db.clone(),
+                user: String::from(user),
+                master_key,
+                slot: None,
+                decoy: false,
+                password_key: Some(password_key),
+                password_params,
+            })
+        }
+
+        fn with_master_key(
+            db: &Database,
+            user: &str,
+            master_key: kdf::SecretKey,
+            slot: Option<usize>,
+        ) -> Self {
+            Self {
+                db: db.clone(),
+                user: String::from(user),
+                master_key,
+                slot,
+                decoy: false,
+                password_key: None,
+                password_params: KdfParams::none(),
+            }
+        }
+
+        pub fn db(&self) -> &Database {
+            &self.db
+        }
+
+        pub fn user(&self) -> &str {
+            &self.user
+        }
+
+        pub fn master_key(&self) -> &kdf::SecretKey {
+            &self.master_key
+        }
+
+        // The key slot this session was opened with.
+        pub fn slot(&self) -> Option<usize> {
+            self.slot
+        }
+
+        pub fn is_decoy(&self) -> bool {
+            self.decoy
+        }
+
+        // Where the journal file kept at `path` in a real journal lives.
+        pub fn journal_path(&self, purpose: &str, path: String) -> Result<String, Error> {
+            if !self.decoy {
+                return Ok(path);
+            }
+            let blob = crypto::derive_blob_name(&self.master_key, purpose).map_err(Error::other)?;
+            Ok(file_system::generate_blob_path(
+                self.db.ids_dir(),
+                &self.user,
+                &blob,
+            ))
+        }
+
+        // Logs sealed before the master key existed use the password-derived
+        // key, which is only at hand for the parameters the master key is
+        // wrapped with.
+        pub fn password_key(
+            &self,
+            params: &KdfParams,
+        ) -> Result<&kdf::SecretKey, UnknownCryptoError> {
+            match &self.password_key {
+                Some(password_key) if *params == self.password_params => Ok(password_key),
+                _ => Err(UnknownCryptoError),
+            }
+        }
+
+        pub fn identity<'a>(&'a self, date: &'a str, log_id: &'a str) -> LogIdentity<'a> {
+            LogIdentity {
+                user: &self.user,
+                date,
+                log_id,
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+// Shamir's secret sharing over GF(256), one polynomial per secret byte. Any
+// `threshold` shares give the secret back; fewer reveal nothing about it. A
+// share is
+//
+//   threshold u8 | x u8 | set [u8; 4] | y [u8; secret length]
+//
+// where x is the share's (non-zero) evaluation point and set is random and
+// the same for every share of one split, so shares of different secrets are
+// not combined into a wrong one.
+pub mod shamir {
+    use orion::errors::UnknownCryptoError;
+    use orion::util::secure_rand_bytes;
+    use zeroize::Zeroizing;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Share {
+        pub threshold: u8,
+        pub x: u8,
+        pub set: [u8; SET_SIZE],
+        pub y: Zeroizing<Vec<u8>>,
+    }
+
+    pub const SET_SIZE: usize = 4;
+
+    impl Share {
+        pub fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+            let mut bytes = Zeroizing::new(Vec::with_capacity(2 + SET_SIZE + self.y.len()));
+            bytes.push(self.threshold);
+            bytes.push(self.x);
+            bytes.extend_from_slice(&self.set);
+            bytes.extend_from_slice(&self.y);
+            bytes
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> Result<Self, UnknownCryptoError> {
+            if bytes.len() < 3 + SET_SIZE || bytes[0] == 0 || bytes[1] == 0 {
+                return Err(UnknownCryptoError);
+            }
+            let mut set = [0u8; SET_SIZE];
+            set.copy_from_slice(&bytes[2..2 + SET_SIZE]);
+
+            Ok(Self {
+                threshold: bytes[0],
+                x: bytes[1],
+                set,
+                y: Zeroizing::new(bytes[2 + SET_SIZE..].to_vec()),
+            })
+        }
+    }
+
+    pub fn split(
+        secret: &[u8],
+        threshold: u8,
+        count: u8,
+    ) -> Result<Vec<Share>, UnknownCryptoError> {
+        if secret.is_empty() || threshold < 2 || count < threshold {
+            return Err(UnknownCryptoError);
+        }
+
+        let mut set = [0u8; SET_SIZE];
+        secure_rand_bytes(&mut set)?;
+        let mut shares: Vec<Share> = (1..=count)
+            .map(|x| Share {
+                threshold,
+                x,
+                set,
+                y: Zeroizing::new(Vec::with_capacity(secret.len())),
+            })
+            .collect();
+        let mut coefficients = vec![0u8; threshold as usize];
+        for byte in secret {
+            coefficients[0] = *byte;
+            secure_rand_bytes(&mut coefficients[1..])?;
+            for share in shares.iter_mut() {
+                share.y.push(evaluate(&coefficients, share.x));
+            }
+        }
+
+        Ok(shares)
+    }
+
+    pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>, UnknownCryptoError> {
+        let first = shares.first().ok_or(UnknownCryptoError)?;
+        let threshold = first.threshold as usize;
+        if shares.len() < threshold {
+            return Err(UnknownCryptoError);
+        }
+        let shares = &shares[..threshold];
+        for (i, share) in shares.iter().enumerate() {
+            if share.threshold != first.threshold
+                || share.set != first.set
+                || share.x == 0
+                || share.y.len() != first.y.len()
+                || shares[..i].iter().any(|other| other.x == share.x)
+            {
+                return Err(UnknownCryptoError);
+            }
+        }
+
+        // Lagrange interpolation at x = 0; subtraction is xor in GF(256).
+        let mut secret = Zeroizing::new(vec![0u8; first.y.len()]);
+        for (j, share) in shares.iter().enumerate() {
+            let mut basis = 1u8;
+            for (m, other) in shares.iter().enumerate() {
+                if m != j {
+                    basis = mul(basis, mul(other.x, inverse(other.x ^ share.x)));
+                }
+            }
+            for (byte, y) in secret.iter_mut().zip(share.y.iter()) {
+                *byte ^= mul(*y, basis);
+            }
+        }
+
+        Ok(secret)
+    }
+
+    fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+        coefficients
+            .iter()
+            .rev()
+            .fold(0u8, |acc, coefficient| mul(acc, x) ^ coefficient)
+    }
+
+    // Multiplication modulo x^8 + x^4 + x^3 + x + 1, without data dependent
+    // branches.
+    fn mul(mut a: u8, mut b: u8) -> u8 {
+        let mut product = 0u8;
+        for _ in 0..8 {
+            product ^= a & 0u8.wrapping_sub(b & 1);
+            let carry = 0u8.wrapping_sub(a >> 7);
+            a = (a << 1) ^ (carry & 0x1b);
+            b >>= 1;
+        }
+        product
+    }
+
+    // a^254 is the inverse of a in GF(256).
+    fn inverse(a: u8) -> u8 {
+        let mut result = 1u8;
+        let mut base = a;
+        let mut exponent = 254u8;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = mul(result, base);
+            }
+            base = mul(base, base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+        #[test]
+        fn any_threshold_of_shares_recovers_the_secret() {
+            for (threshold, count) in [(2, 2), (2, 3), (3, 5), (5, 5), (4, 10), (255, 255)] {
+                let shares = split(SECRET, threshold, count).unwrap();
+                assert_eq!(shares.len(), count as usize);
+
+                let threshold = threshold as usize;
+                assert_eq!(combine(&shares[..threshold]).unwrap().as_slice(), SECRET);
+                assert_eq!(
+                    combine(&shares[shares.len() - threshold..])
+                        .unwrap()
+                        .as_slice(),
+                    SECRET
+                );
+                let mut reversed = shares.clone();
+                reversed.reverse();
+                assert_eq!(combine(&reversed).unwrap().as_slice(), SECRET);
+            }
+        }
+
+        #[test]
+        fn fewer_shares_than_the_threshold_fail() {
+            let shares = split(SECRET, 3, 5).unwrap();
+            assert!(combine(&shares[..2]).is_err());
+            assert!(combine(&[]).is_err());
+
+            // Claiming a lower threshold interpolates the wrong polynomial.
+            let lowered: Vec<Share> = shares[..2]
+                .iter()
+                .map(|share| Share {
+                    threshold: 2,
+                    ..share.clone()
+                })
+                .collect();
+            assert_ne!(combine(&lowered).unwrap().as_slice(), SECRET);
+        }
+
+        #[test]
+        fn repeated_or_zero_points_are_rejected() {
+            let shares = split(SECRET, 3, 5).unwrap();
+            let repeated = [shares[0].clone(), shares[1].clone(), shares[0].clone()];
+            assert!(combine(&repeated).is_err());
+
+            let mut zero = shares[..3].to_vec();
+            zero[1].x = 0;
+            assert!(combine(&zero).is_err());
+            assert!(Share::from_bytes(&zero[1].to_bytes()).is_err());
+        }
+
+        #[test]
+        fn shares_of_different_secrets_are_rejected() {
+            let shares = split(SECRET, 2, 3).unwrap();
+            let mut other = split(&SECRET[1..], 2, 3).unwrap();
+            other[1].set = shares[1].set;
+            assert!(combine(&[shares[0].clone(), other[1].clone()]).is_err());
+
+            // Shares of another secret of the same length only differ in their set.
+            let other = split(&[0x5a; 32], 2, 3).unwrap();
+            assert!(combine(&[shares[0].clone(), other[1].clone()]).is_err());
+            let same_set = Share {
+                set: shares[0].set,
+                ..other[1].clone()
+            };
+            assert_ne!(
+                combine(&[shares[0].clone(), same_set]).unwrap().as_slice(),
+                SECRET
+            );
+
+            assert!(split(SECRET, 1, 3).is_err());
+            assert!(split(SECRET, 4, 3).is_err());
+            assert!(split(&[], 2, 3).is_err());
+        }
+
+        #[test]
+        fn shares_round_trip_through_bytes() {
+            for share in split(SECRET, 2, 3).unwrap() {
+                assert_eq!(Share::from_bytes(&share.to_bytes()).unwrap(), share);
+            }
+        }
+
+        #[test]
+        fn multiplication_has_inverses() {
+            for a in 1..=255u8 {
+                assert_eq!(mul(a, inverse(a)), 1);
+                assert_eq!(mul(a, 1), a);
+                assert_eq!(mul(a, 0), 0);
+            }
+        }
+    }
+}
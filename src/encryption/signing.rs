@@ -0,0 +1,149 @@
+// Every log is signed with a per-user Ed25519 key, so an exported log can be
+// shown to come unchanged from its journal. The seed is sealed with the master
+// key; the public key is kept in the clear so exports verify without unlocking.
+// Since anyone who can write to the key directory can replace it, a verified
+// export is shown with the key's fingerprint to compare with the one shown at
+// export. A journal without a decoy lists a stand-in key next to its own, so
+// the public keys look the same whether it has one or not, see
+// `key_chain::get_signing_key`.
+// A signature covers the log identity, which stops it from vouching for the
+// same text under another date or position.
+pub mod signing {
+    use crate::database::storage::storage::Database;
+    use crate::encryption::container::container::LogIdentity;
+    use crate::encryption::crypto::crypto;
+    use crate::encryption::session::session::Session;
+    use crate::utils::utils::file_system;
+    use ed25519_compact::{KeyPair, PublicKey, Seed, Signature};
+    use orion::errors::UnknownCryptoError;
+    use orion::hazardous::hash::blake2b::Blake2b;
+    use orion::kdf;
+    use orion::util::secure_rand_bytes;
+    use zeroize::{Zeroize, Zeroizing};
+
+    const CONTEXT: &[u8] = b"crypto_editor log signature";
+    pub const PUBLIC_KEY_SIZE: usize = PublicKey::BYTES;
+
+    // An unsealed key pair; the secret half is wiped when it is dropped.
+    pub struct SigningKey {
+        key_pair: KeyPair,
+    }
+
+    impl SigningKey {
+        pub fn public_key(&self) -> &[u8] {
+            self.key_pair.pk.as_ref()
+        }
+    }
+
+    impl Drop for SigningKey {
+        fn drop(&mut self) {
+            self.key_pair.sk.zeroize();
+        }
+    }
+
+    // Returns the seed sealed with the master key, and the public key.
+    pub fn generate_signing_key(
+        db: &Database,
+        master_key: &kdf::SecretKey,
+        user: &str,
+    ) -> Result<(Vec<u8>, Vec<u8>), UnknownCryptoError> {
+        let mut seed = Zeroizing::new([0u8; Seed::BYTES]);
+        secure_rand_bytes(&mut *seed)?;
+        let sealed = crypto::encrypt_with_master_key(
+            db,
+            master_key,
+            &LogIdentity::signing_key(user),
+            seed.as_ref(),
+        )?;
+        let key_pair = KeyPair::from_seed(Seed::new(*seed));
+
+        Ok((sealed, key_pair.pk.to_vec()))
+    }
+
+    // The public half of a key pair derived from the master key, whose
+    // secret half is never used.
+    pub fn stand_in_public_key(master_key: &kdf::SecretKey) -> Result<Vec<u8>, UnknownCryptoError> {
+        let seed = crypto::derive_subkey(master_key, "stand-in signing key")?;
+        let mut key_pair = KeyPair::from_seed(Seed::new(*seed));
+        key_pair.sk.zeroize();
+
+        Ok(key_pair.pk.to_vec())
+    }
+
+    pub fn open_signing_key(
+        session: &Session,
+        sealed: &[u8],
+    ) -> Result<SigningKey, UnknownCryptoError> {
+        let identity = LogIdentity::signing_key(session.user());
+        let seed = Zeroizing::new(crypto::decrypt_str(session, &identity, sealed)?);
+        let seed = Seed::from_slice(&seed).map_err(|_| UnknownCryptoError)?;
+
+        Ok(SigningKey {
+            key_pair: KeyPair::from_seed(seed),
+        })
+    }
+
+    pub fn sign(signing_key: &SigningKey, identity: &LogIdentity, content: &[u8]) -> Vec<u8> {
+        signing_key
+            .key_pair
+            .sk
+            .sign(message(identity, content), None)
+            .to_vec()
+    }
+
+    pub fn verify(
+        public_key: &[u8],
+        identity: &LogIdentity,
+        content: &[u8],
+        signature: &[u8],
+    ) -> Result<(), UnknownCryptoError> {
+        let public_key = PublicKey::from_slice(public_key).map_err(|_| UnknownCryptoError)?;
+        let signature = Signature::from_slice(signature).map_err(|_| UnknownCryptoError)?;
+        public_key
+            .verify(message(identity, content), &signature)
+            .map_err(|_| UnknownCryptoError)
+    }
+
+    // A short hash of the public key, in groups of four hex digits so it can
+    // be read out and compared by hand.
+    pub fn fingerprint(public_key: &[u8]) -> Result<String, UnknownCryptoError> {
+        let mut state = Blake2b::new(None, 16)?;
+        state.update(public_key)?;
+        let hex = file_system::to_hex(state.finalize()?.as_ref());
+
+        Ok(hex
+            .as_bytes()
+            .chunks(4)
+            .map(|group| String::from_utf8_lossy(group).into_owned())
+            .collect::<Vec<String>>()
+            .join(" "))
+    }
+
+    fn message(identity: &LogIdentity, content: &[u8]) -> Zeroizing<Vec<u8>> {
+        let identity = identity.to_bytes();
+        let mut message = Zeroizing::new(Vec::with_capacity(
+            CONTEXT.len() + identity.len() + content.len(),
+        ));
+        message.extend_from_slice(CONTEXT);
+        message.extend_from_slice(&identity);
+        message.extend_from_slice(content);
+        message
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn fingerprints_are_grouped_and_tell_keys_apart() {
+            let first = KeyPair::from_seed(Seed::new([1u8; Seed::BYTES]));
+            let second = KeyPair::from_seed(Seed::new([2u8; Seed::BYTES]));
+            let shown = fingerprint(first.pk.as_ref()).unwrap();
+
+            assert_eq!(shown.len(), 39);
+            assert!(shown.split(' ').all(|group| group.len() == 4));
+            assert_eq!(shown, fingerprint(first.pk.as_ref()).unwrap());
+            assert_ne!(shown, fingerprint(second.pk.as_ref()).unwrap());
+        }
+    }
+}
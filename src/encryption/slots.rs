@@ -0,0 +1,131 @@
+// A key slot is one independent way into a journal. Every slot seals the same
+// master key: password slots under a key derived from the password (mixed with
+// a keyfile for `PasswordKeyfile`) and the slot's own salt, recovery slots
+// directly under the recovery key. On disk a slot is
+//
+//   kind u8 | salt [u8; 16] | sealed master key (see `container`)
+//
+// The sealed part is byte for byte what `master.key` and `recovery.key` held
+// before slots existed, so those files are moved into slots unchanged.
+pub mod slots {
+    use crate::encryption::container::container::{self, Container, KdfParams, KeyRef};
+    use crate::encryption::crypto::crypto;
+    use orion::errors::UnknownCryptoError;
+    use orion::kdf;
+
+    pub const SALT_SIZE: usize = 16;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum SlotKind {
+        Password = 1,
+        PasswordKeyfile = 2,
+        Recovery = 3,
+    }
+
+    impl SlotKind {
+        pub fn describe(&self) -> &'static str {
+            match self {
+                SlotKind::Password => "Password",
+                SlotKind::PasswordKeyfile => "Password and keyfile",
+                SlotKind::Recovery => "Recovery key",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Slot {
+        pub kind: SlotKind,
+        pub salt: [u8; SALT_SIZE],
+        pub sealed: Vec<u8>,
+    }
+
+    impl Slot {
+        pub fn seal_password(
+            user: &str,
+            secret: &str,
+            kind: SlotKind,
+            master_key: &kdf::SecretKey,
+            params: &KdfParams,
+        ) -> Result<Self, UnknownCryptoError> {
+            if kind == SlotKind::Recovery {
+                return Err(UnknownCryptoError);
+            }
+            let mut salt = [0u8; SALT_SIZE];
+            salt.copy_from_slice(kdf::Salt::default().as_ref());
+            let sealed =
+                crypto::wrap_master_key_with_salt(user, secret, &salt, master_key, params)?;
+
+            Ok(Self { kind, salt, sealed })
+        }
+
+        pub fn seal_recovery(
+            user: &str,
+            recovery_key: &kdf::SecretKey,
+            master_key: &kdf::SecretKey,
+        ) -> Result<Self, UnknownCryptoError> {
+            Ok(Self {
+                kind: SlotKind::Recovery,
+                salt: [0u8; SALT_SIZE],
+                sealed: crypto::wrap_master_key_for_recovery(user, recovery_key, master_key)?,
+            })
+        }
+
+        pub fn params(&self) -> Option<KdfParams> {
+            match container::parse(&self.sealed) {
+                Ok(Container::V1(header, _)) if header.key == KeyRef::Password => Some(header.kdf),
+                _ => None,
+            }
+        }
+
+        pub fn open_with_secret(
+            &self,
+            user: &str,
+            secret: &str,
+        ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+            let params = self.params().ok_or(UnknownCryptoError)?;
+            let derived_key = crypto::derive_password_key(secret, &self.salt, &params)?;
+
+            crypto::unwrap_master_key(user, &derived_key, &self.sealed)
+        }
+
+        pub fn open_with_recovery(
+            &self,
+            user: &str,
+            recovery_key: &kdf::SecretKey,
+        ) -> Result<kdf::SecretKey, UnknownCryptoError> {
+            if self.kind != SlotKind::Recovery {
+                return Err(UnknownCryptoError);
+            }
+
+            crypto::unwrap_master_key_with_recovery(user, recovery_key, &self.sealed)
+        }
+
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::with_capacity(1 + SALT_SIZE + self.sealed.len());
+            bytes.push(self.kind as u8);
+            bytes.extend_from_slice(&self.salt);
+            bytes.extend_from_slice(&self.sealed);
+            bytes
+        }
+
+        pub fn from_bytes(bytes: &[u8]) -> Result<Self, UnknownCryptoError> {
+            if bytes.len() <= 1 + SALT_SIZE {
+                return Err(UnknownCryptoError);
+            }
+            let kind = match bytes[0] {
+                1 => SlotKind::Password,
+                2 => SlotKind::PasswordKeyfile,
+                3 => SlotKind::Recovery,
+                _ => return Err(UnknownCryptoError),
+            };
+            let mut salt = [0u8; SALT_SIZE];
+            salt.copy_from_slice(&bytes[1..1 + SALT_SIZE]);
+
+            Ok(Self {
+                kind,
+                salt,
+                sealed: bytes[1 + SALT_SIZE..].to_vec(),
+            })
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// Large logs are sealed as a stream of chunks instead of one AEAD message, so
+// neither side has to hold the whole ciphertext in memory:
+//
+//   header (cipher = XChaCha20Poly1305Stream) | chunk | chunk | ... | final chunk
+//   chunk = length u32 | sealed chunk
+//
+// Each chunk seals CHUNK_SIZE bytes of plaintext with orion's streaming
+// XChaCha20-Poly1305 and the header plus log identity as associated data. The
+// last chunk carries the Finish tag, so a stream cut short at a chunk boundary
+// is rejected. Its plaintext is framed like a whole log and, unless LOG_PADDING
+// is `none`, padded to a full chunk, which leaves the size of a stream rounded
+// to CHUNK_SIZE. Chunks are not compressed.
+pub mod stream {
+    use crate::encryption::container::container::{
+        CipherSuite, Header, KdfParams, KeyRef, LogIdentity, HEADER_SIZE,
+    };
+    use crate::encryption::padding::padding::{self, Padding, FRAME_SIZE};
+    use orion::hazardous::aead::streaming::{
+        Nonce, SecretKey, StreamTag, StreamXChaCha20Poly1305, ABYTES,
+    };
+    use orion::kdf;
+    use std::convert::TryInto;
+    use std::io::prelude::*;
+    use std::io::{Error, ErrorKind};
+    use zeroize::Zeroizing;
+
+    pub const CHUNK_SIZE: usize = 64 * 1024;
+    const MAX_SEALED_CHUNK: usize = CHUNK_SIZE + FRAME_SIZE + ABYTES;
+
+    pub fn is_stream(header: &[u8]) -> bool {
+        match Header::from_bytes(header) {
+            Ok(header) => header.cipher == CipherSuite::XChaCha20Poly1305Stream,
+            Err(_) => false,
+        }
+    }
+
+    pub fn encrypt_stream<R: Read, W: Write + ?Sized>(
+        master_key: &kdf::SecretKey,
+        identity: &LogIdentity,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        let mut sealing = StreamWriter::new(master_key, identity, writer)?;
+        loop {
+            sealing.seal_full_chunk()?;
+            let len = read_full(reader, &mut sealing.chunk[sealing.len..])?;
+            if len == 0 {
+                break;
+            }
+            sealing.len += len;
+        }
+
+        sealing.finish()
+    }
+
+    // Seals what is written to it chunk by chunk, for plaintext that comes
+    // out of a writer rather than a reader. Nothing is sealed as the last
+    // chunk until `finish`, so a stream that is dropped half way does not
+    // open.
+    pub struct StreamWriter<'a, W: Write + ?Sized> {
+        writer: &'a mut W,
+        state: StreamXChaCha20Poly1305,
+        associated_data: Vec<u8>,
+        chunk: Zeroizing<Vec<u8>>,
+        len: usize,
+    }
+
+    impl<'a, W: Write + ?Sized> StreamWriter<'a, W> {
+        pub fn new(
+            master_key: &kdf::SecretKey,
+            identity: &LogIdentity,
+            writer: &'a mut W,
+        ) -> Result<Self, Error> {
+            let mut header = Header::new(KdfParams::none(), KeyRef::MasterKey).map_err(invalid)?;
+            header.cipher = CipherSuite::XChaCha20Poly1305Stream;
+            let state = new_state(master_key, &header)?;
+            writer.write_all(&header.to_bytes())?;
+
+            Ok(Self {
+                writer,
+                state,
+                associated_data: header.associated_data(identity),
+                chunk: Zeroizing::new(vec![0u8; CHUNK_SIZE]),
+                len: 0,
+            })
+        }
+
+        // Seals the rest as the last chunk.
+        pub fn finish(mut self) -> Result<(), Error> {
+            let padding = match Padding::from_env() {
+                Padding::None => Padding::None,
+                _ => Padding::Bucket(CHUNK_SIZE + FRAME_SIZE),
+            };
+            let framed = Zeroizing::new(
+                padding::pad(0, &self.chunk[..self.len], &padding).map_err(invalid)?,
+            );
+            write_chunk(
+                self.writer,
+                &mut self.state,
+                &framed,
+                &self.associated_data,
+                StreamTag::Finish,
+            )?;
+
+            self.writer.flush()
+        }
+
+        // A full chunk is only sealed once more plaintext follows it, since
+        // the last chunk has to carry the Finish tag.
+        fn seal_full_chunk(&mut self) -> Result<(), Error> {
+            if self.len < CHUNK_SIZE {
+                return Ok(());
+            }
+            write_chunk(
+                self.writer,
+                &mut self.state,
+                &self.chunk,
+                &self.associated_data,
+                StreamTag::Message,
+            )?;
+            self.len = 0;
+            Ok(())
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for StreamWriter<'_, W> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            self.seal_full_chunk()?;
+            let len = buf.len().min(CHUNK_SIZE - self.len);
+            self.chunk[self.len..self.len + len].copy_from_slice(&buf[..len]);
+            self.len += len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            self.writer.flush()
+        }
+    }
+
+    // Plaintext is written out as each chunk is authenticated; on an error
+    // the caller has to discard what was written so far.
+    pub fn decrypt_stream<R: Read, W: Write + ?Sized>(
+        master_key: &kdf::SecretKey,
+        identity: &LogIdentity,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        let mut header_bytes = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut header_bytes)?;
+        let header = Header::from_bytes(&header_bytes).map_err(invalid)?;
+        if header.cipher != CipherSuite::XChaCha20Poly1305Stream || header.key != KeyRef::MasterKey
+        {
+            return Err(invalid(()));
+        }
+        let associated_data = header.associated_data(identity);
+        let mut state = new_state(master_key, &header)?;
+
+        let mut sealed = vec![0u8; MAX_SEALED_CHUNK];
+        let mut opened = Zeroizing::new(vec![0u8; MAX_SEALED_CHUNK - ABYTES]);
+        loop {
+            let mut length = [0u8; 4];
+            if read_full(reader, &mut length)? != length.len() {
+                return Err(invalid(()));
+            }
+            let len = u32::from_le_bytes(length) as usize;
+            if !(ABYTES..=MAX_SEALED_CHUNK).contains(&len)
+                || read_full(reader, &mut sealed[..len])? != len
+            {
+                return Err(invalid(()));
+            }
+
+            let opened = &mut opened[..len - ABYTES];
+            let tag = state
+                .open_chunk(&sealed[..len], Some(&associated_data), opened)
+                .map_err(invalid)?;
+            match tag {
+                StreamTag::Message if opened.len() == CHUNK_SIZE => writer.write_all(opened)?,
+                StreamTag::Finish => {
+                    if read_full(reader, &mut [0u8; 1])? != 0 {
+                        return Err(invalid(()));
+                    }
+                    let (_, data) = padding::unpad(opened).map_err(invalid)?;
+                    writer.write_all(data)?;
+                    return writer.flush();
+                }
+                _ => return Err(invalid(())),
+            }
+        }
+    }
+
+    fn new_state(
+        master_key: &kdf::SecretKey,
+        header: &Header,
+    ) -> Result<StreamXChaCha20Poly1305, Error> {
+        let secret_key =
+            SecretKey::from_slice(master_key.unprotected_as_bytes()).map_err(invalid)?;
+        let nonce = Nonce::from_slice(&header.nonce).map_err(invalid)?;
+
+        Ok(StreamXChaCha20Poly1305::new(&secret_key, &nonce))
+    }
+
+    fn write_chunk<W: Write + ?Sized>(
+        writer: &mut W,
+        state: &mut StreamXChaCha20Poly1305,
+        plaintext: &[u8],
+        associated_data: &[u8],
+        tag: StreamTag,
+    ) -> Result<(), Error> {
+        let mut sealed = vec![0u8; plaintext.len() + ABYTES];
+        state
+            .seal_chunk(plaintext, Some(associated_data), &mut sealed, tag)
+            .map_err(invalid)?;
+        let length: u32 = sealed.len().try_into().map_err(invalid)?;
+
+        writer.write_all(&length.to_le_bytes())?;
+        writer.write_all(&sealed)
+    }
+
+    // Reads until the buffer is full or the reader is exhausted.
+    fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(why) if why.kind() == ErrorKind::Interrupted => continue,
+                Err(why) => return Err(why),
+            }
+        }
+
+        Ok(filled)
+    }
+
+    fn invalid<E>(_: E) -> Error {
+        Error::new(ErrorKind::InvalidData, "Stream authentication failed")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn identity() -> LogIdentity<'static> {
+            LogIdentity {
+                user: "user",
+                date: "01_01_2026",
+                log_id: "0",
+            }
+        }
+
+        fn plaintext(len: usize) -> Vec<u8> {
+            (0..len).map(|i| (i % 251) as u8).collect()
+        }
+
+        fn open(master_key: &kdf::SecretKey, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+            let mut opened = Vec::new();
+            decrypt_stream(master_key, &identity(), &mut &sealed[..], &mut opened)?;
+            Ok(opened)
+        }
+
+        #[test]
+        fn streams_round_trip_at_chunk_boundaries() {
+            let master_key = kdf::SecretKey::default();
+            for len in [
+                0,
+                1,
+                CHUNK_SIZE - 1,
+                CHUNK_SIZE,
+                CHUNK_SIZE + 1,
+                3 * CHUNK_SIZE,
+            ] {
+                let data = plaintext(len);
+                let mut sealed = Vec::new();
+                encrypt_stream(&master_key, &identity(), &mut &data[..], &mut sealed).unwrap();
+                assert!(is_stream(&sealed));
+                assert_eq!(open(&master_key, &sealed).unwrap(), data);
+            }
+        }
+
+        #[test]
+        fn writer_seals_what_is_written_in_pieces() {
+            let master_key = kdf::SecretKey::default();
+            for len in [0, CHUNK_SIZE, 2 * CHUNK_SIZE + 7] {
+                let data = plaintext(len);
+                let mut sealed = Vec::new();
+                let mut sealing = StreamWriter::new(&master_key, &identity(), &mut sealed).unwrap();
+                for piece in data.chunks(1000) {
+                    sealing.write_all(piece).unwrap();
+                }
+                sealing.finish().unwrap();
+                assert_eq!(open(&master_key, &sealed).unwrap(), data);
+            }
+        }
+
+        #[test]
+        fn rejects_cut_short_or_moved_streams() {
+            let master_key = kdf::SecretKey::default();
+            let data = plaintext(2 * CHUNK_SIZE + 7);
+            let mut sealed = Vec::new();
+            encrypt_stream(&master_key, &identity(), &mut &data[..], &mut sealed).unwrap();
+
+            let first_chunk = HEADER_SIZE + 4 + CHUNK_SIZE + ABYTES;
+            assert!(open(&master_key, &sealed[..first_chunk]).is_err());
+            assert!(open(&master_key, &sealed[..sealed.len() - 1]).is_err());
+            assert!(open(&kdf::SecretKey::default(), &sealed).is_err());
+
+            let moved = LogIdentity {
+                log_id: "1",
+                ..identity()
+            };
+            let mut opened = Vec::new();
+            assert!(decrypt_stream(&master_key, &moved, &mut &sealed[..], &mut opened).is_err());
+
+            let mut unfinished = Vec::new();
+            let mut sealing = StreamWriter::new(&master_key, &identity(), &mut unfinished).unwrap();
+            sealing.write_all(&data).unwrap();
+            drop(sealing);
+            assert!(open(&master_key, &unfinished).is_err());
+        }
+    }
+}
@@ -1,19 +1,40 @@
+#![allow(clippy::module_inception)]
+use database::storage::storage::{Database, FsStorage, Storage};
 use std::env;
+use std::sync::Arc;
 
 pub mod utils {
     pub mod list_tools;
+    pub mod process;
     pub mod utils;
 }
 pub use utils::*;
 
 pub mod encryption {
+    pub mod compression;
+    pub mod container;
     pub mod crypto;
+    pub mod kdf_policy;
+    pub mod padding;
+    pub mod recovery;
+    pub mod session;
+    pub mod shamir;
+    pub mod signing;
+    pub mod slots;
+    pub mod stream;
     pub use crypto::crypto::*;
 }
 
 pub mod database {
     pub mod database_handler;
+    pub mod export;
+    pub mod index;
     pub mod keychain;
+    pub mod manifest;
+    pub mod profile;
+    pub mod storage;
+    pub mod throttle;
+    pub mod vault;
     pub use database_handler::*;
     pub use keychain::*;
 }
@@ -26,9 +47,27 @@ pub mod menu {
 }
 pub use menu::menu::menu::*;
 
+const IDS_DIR: &str = "./src/database/logs";
+const KEYS_DIR: &str = "./src/database/keys";
+
 fn main() {
-    env::set_var("DATABASE_IDS_DIR", "./src/database/logs");
-    env::set_var("DATABASE_KEYS_DIR", "./src/database/keys");
+    if process::process::disable_core_dumps().is_err() {
+        println!("Unable to disable core dumps");
+    }
+    if let Err(why) = process::process::lock_memory() {
+        println!(
+            "Unable to lock memory ({}), decrypted logs may be swapped to disk",
+            why
+        );
+    }
+
+    let storage: Arc<dyn Storage> = match env::var("DATABASE_VAULT") {
+        Ok(vault_path) => match open_vault(&vault_path, &[IDS_DIR, KEYS_DIR]) {
+            Some(vault) => Arc::new(vault),
+            None => return,
+        },
+        Err(_) => Arc::new(FsStorage),
+    };
 
-    run();
+    run(&Database::new(storage, IDS_DIR, KEYS_DIR));
 }
@@ -1,21 +1,30 @@
 pub mod menu {
-    use std::io::{self, Write};
+    use std::io::{self, Read, Write};
     use termion::clear;
     use termion::color;
-    use termion::input::TermRead;
+    use termion::raw::IntoRawMode;
 
     use super::super::super::database::database_handler::*;
+    use super::super::super::database::export::*;
     use super::super::super::database::keychain::*;
+    use super::super::super::database::profile::profile;
+    use super::super::super::database::storage::storage::{self, Database, FsStorage, Storage};
+    use super::super::super::database::throttle::throttle::{Record, Status};
+    use super::super::super::database::vault::vault::VaultStorage;
+    use super::super::super::encryption::compression::compression::Compression;
+    use super::super::super::encryption::session::session::Session;
     use super::super::super::CryptoEditor;
 
     use super::super::super::utils::list_tools;
 
-    use chrono::Local;
+    use chrono::{Local, TimeZone};
+    use std::path::Path;
+    use zeroize::Zeroizing;
 
     pub fn read_input() -> Option<String> {
         let mut input = String::new();
 
-        if let Ok(_) = io::stdin().read_line(&mut input) {
+        if io::stdin().read_line(&mut input).is_ok() {
             println!("{}", clear::All);
             print!("{}", termion::cursor::Goto(1, 1));
             return Some(input[0..input.len() - 1].to_string());
@@ -24,18 +33,47 @@ pub mod menu {
         None
     }
 
-    pub fn read_password() -> Option<String> {
+    pub fn read_password() -> Option<Zeroizing<String>> {
+        read_password_with_prompt("Password")
+    }
+
+    // Secrets are typed without echo, like with termion's `read_passwd`, but
+    // into a buffer that is wiped and never grows; input past its size is
+    // dropped.
+    pub fn read_password_with_prompt(prompt: &str) -> Option<Zeroizing<String>> {
+        const MAX_SECRET_LEN: usize = 1024;
         let stdout = io::stdout();
         let mut stdout = stdout.lock();
-        let stdin = io::stdin();
-        let mut stdin = stdin.lock();
 
-        stdout.write_all(b"Password: ").unwrap();
+        write!(stdout, "{}: ", prompt).unwrap();
         stdout.flush().unwrap();
 
-        let pass = stdin.read_passwd(&mut stdout).unwrap();
+        let mut input = Zeroizing::new(Vec::with_capacity(MAX_SECRET_LEN));
+        let mut complete = false;
+        if let Ok(_raw) = (&mut stdout).into_raw_mode() {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Err(_) | Ok(0) | Ok(3) | Ok(4) => break,
+                    Ok(0x7f) => {
+                        input.pop();
+                    }
+                    Ok(b'\n') | Ok(b'\r') => {
+                        complete = true;
+                        break;
+                    }
+                    Ok(byte) if input.len() < MAX_SECRET_LEN => input.push(byte),
+                    Ok(_) => (),
+                }
+            }
+        }
         println!();
-        pass
+        if !complete || std::str::from_utf8(&input).is_err() {
+            return None;
+        }
+
+        Some(Zeroizing::new(
+            String::from_utf8(std::mem::take(&mut *input)).unwrap(),
+        ))
     }
 
     pub fn read_with_prompt(prompt: &str) -> Option<String> {
@@ -44,8 +82,149 @@ pub mod menu {
         read_input()
     }
 
-    pub fn run() {
-        let mut password: String = String::new();
+    type Keyfile = Zeroizing<Vec<u8>>;
+
+    // Asks until the keyfile can be read. An empty path means no keyfile.
+    pub fn read_keyfile(prompt: &str) -> Option<Keyfile> {
+        loop {
+            let path = read_with_prompt(prompt)?;
+            if path.is_empty() {
+                return None;
+            }
+            match key_chain::read_keyfile(&path) {
+                Ok(keyfile) => return Some(keyfile),
+                Err(why) => {
+                    print!("{}", color::Fg(color::Red));
+                    println!("Unable to read keyfile: {}", why);
+                    print!("{}", color::Fg(color::Reset));
+                }
+            }
+        }
+    }
+
+    // Reads the password and, when one of the user's key slots takes one, the
+    // keyfile. Returns the combined secret along with the keyfile.
+    fn read_secret(
+        db: &Database,
+        id: &str,
+        prompt: &str,
+    ) -> Option<(Zeroizing<String>, Option<Keyfile>)> {
+        let password = read_password_with_prompt(prompt)?;
+        let keyfile = if key_chain::requires_keyfile(db, id) {
+            read_keyfile("Keyfile path (leave empty for none)")
+        } else {
+            None
+        };
+
+        let secret =
+            key_chain::credential_secret(&password, keyfile.as_deref().map(Vec::as_slice)).ok()?;
+        Some((secret, keyfile))
+    }
+
+    // Opens the vault at `path`, creating it when it does not exist yet. A
+    // new vault takes in the journals kept as files in `dirs` so far.
+    pub fn open_vault(path: &str, dirs: &[&str; 2]) -> Option<VaultStorage> {
+        if Path::new(path).exists() {
+            for _ in 0..3 {
+                if !check_throttle(Record::vault(path), false) {
+                    return None;
+                }
+                let password = read_password_with_prompt("Vault password").unwrap_or_default();
+                match VaultStorage::open(path, &password) {
+                    Ok(vault) => return Some(vault),
+                    Err(why) => {
+                        print!("{}", color::Fg(color::Red));
+                        println!("Unable to open vault: {}", why);
+                        print!("{}", color::Fg(color::Reset));
+                    }
+                }
+            }
+            return None;
+        }
+
+        print!("{}", color::Fg(color::Green));
+        println!("Creating a new vault at {}", path);
+        print!("{}", color::Fg(color::Reset));
+        let password = read_password_with_prompt("New vault password").unwrap_or_default();
+        let confirmation =
+            read_password_with_prompt("New vault password again").unwrap_or_default();
+        if password.is_empty() || password != confirmation {
+            print!("{}", color::Fg(color::Red));
+            println!("Passwords do not match!");
+            print!("{}", color::Fg(color::Reset));
+            return None;
+        }
+
+        let vault = match VaultStorage::create(path, &password) {
+            Ok(vault) => vault,
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to create vault: {}", why);
+                print!("{}", color::Fg(color::Reset));
+                return None;
+            }
+        };
+        // A vault that misses some of the journals would be opened as it is
+        // on the next start, so it is removed to have the import run again.
+        let count = match storage::copy(&FsStorage, &vault, dirs) {
+            Ok(count) => count,
+            Err(why) => {
+                drop(vault);
+                let _ = FsStorage.delete(path);
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to copy the journals into the vault: {}", why);
+                print!("{}", color::Fg(color::Reset));
+                return None;
+            }
+        };
+        if count > 0 {
+            println!("Copied {} files into the vault.", count);
+            wipe_imported_files(path, dirs);
+        }
+        Some(vault)
+    }
+
+    // The files a vault was filled from still show the names and layout the
+    // vault hides, so they can be wiped once they are in it.
+    fn wipe_imported_files(vault_path: &str, dirs: &[&str]) {
+        let vault_path = Path::new(vault_path)
+            .canonicalize()
+            .unwrap_or_else(|_| Path::new(vault_path).to_path_buf());
+        let holding_vault = dirs.iter().find(|dir| match Path::new(dir).canonicalize() {
+            Ok(dir) => vault_path.starts_with(dir),
+            Err(_) => false,
+        });
+        if let Some(dir) = holding_vault {
+            println!(
+                "The vault is inside {}, so the files there are left in place.",
+                dir
+            );
+            return;
+        }
+
+        print!("{}", color::Fg(color::Green));
+        println!(
+            "Wipe the journal files left in {} and {}? (y/n)",
+            dirs[0], dirs[1]
+        );
+        print!("{}", color::Fg(color::Reset));
+        match read_input() {
+            Some(answer) if answer.trim().eq_ignore_ascii_case("y") => {
+                match storage::wipe_dirs(&FsStorage, dirs) {
+                    Ok(count) => println!("Wiped {} files.", count),
+                    Err(why) => {
+                        print!("{}", color::Fg(color::Red));
+                        println!("Unable to wipe the old files: {}", why);
+                        print!("{}", color::Fg(color::Reset));
+                    }
+                }
+            }
+            _ => println!("The old files are left in place."),
+        }
+    }
+
+    pub fn run(db: &Database) {
+        let mut password = Zeroizing::new(String::new());
         let mut input: String = String::new();
 
         println!("{}", clear::All);
@@ -55,9 +234,11 @@ pub mod menu {
         print!("{}", color::Fg(color::Reset));
         loop {
             print!("{}", color::Fg(color::Blue));
-            println!("{}) {}", '1', "Access logs");
-            println!("{}) {}", '2', "Create user");
-            println!("{}) {}", '3', "Quit");
+            println!("1) Access logs");
+            println!("2) Create user");
+            println!("3) Recover account");
+            println!("4) Verify exported log");
+            println!("5) Quit");
             print!("{}", color::Fg(color::Reset));
 
             match read_input() {
@@ -65,7 +246,7 @@ pub mod menu {
                 None => println!("Didn't work!"),
             }
             if input == "1" {
-                if let Some(ids) = key_chain::get_key_ids() {
+                if let Some(ids) = key_chain::get_key_ids(db) {
                     print!("{}", color::Fg(color::Green));
                     println!("Following users are active: ");
                     print!("{}", color::Fg(color::Reset));
@@ -76,7 +257,9 @@ pub mod menu {
                     }
                     match process_option(input.clone(), ids) {
                         Some(chosen_id) => {
-                            access_logs(chosen_id.1.as_ref());
+                            if let Some(session) = unlock(db, chosen_id.1.as_ref()) {
+                                access_logs(&session);
+                            }
                             break;
                         }
                         None => {
@@ -93,6 +276,12 @@ pub mod menu {
                     Some(c) => input = c,
                     None => println!("Didn't work!"),
                 }
+                if key_chain::user_exists(db, &input) {
+                    print!("{}", color::Fg(color::Red));
+                    println!("User {} already exists!", input);
+                    print!("{}", color::Fg(color::Reset));
+                    continue;
+                }
 
                 match read_password() {
                     Some(c) => password = c,
@@ -102,23 +291,59 @@ pub mod menu {
                 print!("{}", color::Fg(color::Green));
                 println!("Insert password again.");
                 print!("{}", color::Fg(color::Reset));
-                let mut temp_pass = String::new();
+                let mut temp_pass = Zeroizing::new(String::new());
                 match read_password() {
                     Some(c) => temp_pass = c,
                     None => println!("Didn't work!"),
                 }
 
-                if temp_pass.contains(&password) {
+                if temp_pass.contains(password.as_str()) {
+                    let keyfile = read_keyfile("Keyfile path (leave empty for none)");
+                    let secret = key_chain::credential_secret(
+                        &password,
+                        keyfile.as_deref().map(Vec::as_slice),
+                    )
+                    .unwrap_or_default();
                     println!("Creating user...");
-                    match key_chain::create_user(&input, &password) {
+                    match key_chain::create_user(
+                        db,
+                        &input,
+                        &password,
+                        keyfile.as_deref().map(Vec::as_slice),
+                    ) {
                         Ok(_) => {
                             println!("{}", clear::All);
                             print!("{}", termion::cursor::Goto(1, 1));
-                            access_logs(&input)
+                            match Session::unlock(db, &input, &secret) {
+                                Ok(session) => {
+                                    offer_recovery_key(&session);
+                                    access_logs(&session);
+                                }
+                                Err(_) => println!("Error unlocking user!"),
+                            }
                         }
-                        Err(_) => println!("Error creating user!"),
+                        Err(why) => println!("Error creating user: {}", why),
+                    }
+                }
+            } else if input == "3" {
+                if let Some(ids) = key_chain::get_key_ids(db) {
+                    print!("{}", color::Fg(color::Green));
+                    println!("Which account would you like to recover?");
+                    print!("{}", color::Fg(color::Reset));
+                    display_options(&ids);
+                    match read_input() {
+                        Some(c) => input = c,
+                        None => println!("Didn't work!"),
+                    }
+                    match process_option(input.clone(), ids) {
+                        Some(chosen_id) => recover_account(db, &chosen_id.1),
+                        None => println!("Invalid input. Choose one of the options."),
                     }
+                } else {
+                    println!("There are no users.");
                 }
+            } else if input == "4" {
+                verify_export(db);
             } else {
                 println!("Goodbye!");
                 break;
@@ -128,6 +353,169 @@ pub mod menu {
         print!("{}", color::Fg(color::Reset));
     }
 
+    fn offer_recovery_key(session: &Session) {
+        print!("{}", color::Fg(color::Green));
+        println!("Would you like to create a recovery key? (y/n)");
+        print!("{}", color::Fg(color::Reset));
+
+        if let Some(answer) = read_input() {
+            if answer.trim().eq_ignore_ascii_case("y") {
+                create_recovery_key(session);
+            }
+        }
+    }
+
+    fn create_recovery_key(session: &Session) {
+        match key_chain::create_recovery_key(session) {
+            Ok(phrase) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Write down your recovery phrase and keep it somewhere safe.");
+                println!("It is shown only once. Remove its key slot to revoke it.");
+                print!("{}", color::Fg(color::Reset));
+                println!("{}", phrase.as_str());
+                read_with_prompt("Press enter once you have written it down");
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to create a recovery key: {}", why);
+                print!("{}", color::Fg(color::Reset));
+            }
+        }
+    }
+
+    fn read_recovery_key() -> Option<orion::kdf::SecretKey> {
+        let options = vec![
+            String::from("Recovery phrase"),
+            String::from("Recovery shares"),
+        ];
+        display_options(&options);
+        let input = read_input()?;
+
+        let recovery_key = match process_option(input, options) {
+            Some((0, _)) => {
+                let phrase = read_password_with_prompt("Recovery phrase")?;
+                key_chain::recovery_key_from_phrase(&phrase)
+            }
+            Some(_) => {
+                let first = read_password_with_prompt("Share 1")?;
+                match key_chain::parse_recovery_share(&first) {
+                    Ok(threshold) => {
+                        let mut phrases = vec![first];
+                        for n in 2..=threshold {
+                            phrases.push(read_password_with_prompt(&format!(
+                                "Share {} of {}",
+                                n, threshold
+                            ))?);
+                        }
+                        key_chain::recovery_key_from_shares(&phrases)
+                    }
+                    Err(why) => Err(why),
+                }
+            }
+            None => {
+                println!("Invalid input. Choose one of the options.");
+                return None;
+            }
+        };
+
+        match recovery_key {
+            Ok(recovery_key) => Some(recovery_key),
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("{}", why);
+                print!("{}", color::Fg(color::Reset));
+                None
+            }
+        }
+    }
+
+    fn create_recovery_shares(session: &Session) {
+        let count = read_with_prompt("Number of shares").and_then(|c| c.parse::<u8>().ok());
+        let threshold =
+            read_with_prompt("Shares needed to recover").and_then(|c| c.parse::<u8>().ok());
+        let (count, threshold) = match (count, threshold) {
+            (Some(count), Some(threshold)) => (count, threshold),
+            _ => {
+                println!("Invalid input. Enter a number between 2 and 255.");
+                return;
+            }
+        };
+
+        match key_chain::create_recovery_shares(session, threshold, count) {
+            Ok(shares) => {
+                print!("{}", color::Fg(color::Green));
+                println!(
+                    "Hand out each share separately. Any {} of them recover the account.",
+                    threshold
+                );
+                println!("They are shown only once. Remove their key slot to revoke them.");
+                print!("{}", color::Fg(color::Reset));
+                for (n, share) in shares.iter().enumerate() {
+                    println!("Share {} of {}:", n + 1, count);
+                    println!("{}", share.as_str());
+                    read_with_prompt("Press enter for the next share");
+                }
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to create recovery shares: {}", why);
+                print!("{}", color::Fg(color::Reset));
+            }
+        }
+    }
+
+    fn recover_account(db: &Database, id: &str) {
+        if !key_chain::has_recovery_key(db, id) {
+            print!("{}", color::Fg(color::Red));
+            println!("{} does not have a recovery key!", id);
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+
+        let recovery_key = match read_recovery_key() {
+            Some(recovery_key) => recovery_key,
+            None => return,
+        };
+        let new_password = read_password_with_prompt("New password").unwrap_or_default();
+        let confirmation = read_password_with_prompt("New password again").unwrap_or_default();
+
+        if new_password.is_empty() || new_password != confirmation {
+            print!("{}", color::Fg(color::Red));
+            println!("Passwords do not match!");
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+        let keyfile = read_keyfile("Keyfile path (leave empty for none)");
+        let secret =
+            key_chain::credential_secret(&new_password, keyfile.as_deref().map(Vec::as_slice))
+                .unwrap_or_default();
+
+        println!("Recovering account...");
+        match key_chain::recover_account(
+            db,
+            id,
+            &recovery_key,
+            &new_password,
+            keyfile.as_deref().map(Vec::as_slice),
+        ) {
+            Ok(_) => match Session::unlock(db, id, &secret) {
+                Ok(session) => {
+                    print!("{}", color::Fg(color::Green));
+                    println!("Added a key slot for the new password.");
+                    println!("Remove the slot of the forgotten password under Key slots.");
+                    print!("{}", color::Fg(color::Reset));
+                    access_logs(&session);
+                }
+                Err(_) => println!("Error unlocking user!"),
+            },
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to recover account: {}", why);
+                print!("{}", color::Fg(color::Reset));
+            }
+        }
+    }
+
     fn process_option(option: String, options: Vec<String>) -> Option<(usize, String)> {
         if let Ok(idx) = option.parse::<u32>() {
             if idx <= options.len() as u32 && idx != 0 {
@@ -138,21 +526,88 @@ pub mod menu {
         None
     }
 
-    fn access_logs(id: &str) {
+    // Tells the user when the password cannot be tried yet. Only logins lock.
+    fn check_throttle(record: Record, login: bool) -> bool {
+        let status = record.status();
+        print!("{}", color::Fg(color::Red));
+        match status {
+            Status::Open => (),
+            Status::Wait(wait) => println!(
+                "Too many failed attempts. Try again in {} seconds.",
+                wait.as_secs().max(1)
+            ),
+            Status::Locked => {
+                println!("Locked after too many failed attempts.");
+                if login {
+                    println!("Recover the account with its recovery key to unlock it.");
+                }
+            }
+        }
+        print!("{}", color::Fg(color::Reset));
+
+        status == Status::Open
+    }
+
+    fn check_login_throttle(db: &Database, id: &str) -> bool {
+        check_throttle(Record::login(db, id), true)
+    }
+
+    fn check_log_throttle(session: &Session) -> bool {
+        check_throttle(Record::log_passwords(session.db(), session.user()), false)
+    }
+
+    fn unlock(db: &Database, id: &str) -> Option<Session> {
+        loop {
+            if !check_login_throttle(db, id) {
+                return None;
+            }
+            match read_secret(db, id, "Password") {
+                Some((c, _)) => {
+                    if let Ok(session) = key_chain::unlock(db, id, &c) {
+                        if key_chain::needs_kdf_upgrade(&session) {
+                            println!("Upgrading key derivation parameters...");
+                            if let Err(why) = key_chain::upgrade_kdf_params(&session, &c) {
+                                println!("Unable to upgrade key derivation parameters: {}", why);
+                            }
+                        }
+                        return Some(session);
+                    }
+                    print!("{}", color::Fg(color::Green));
+                    println!("Incorrect password!");
+                    print!("{}", color::Fg(color::Reset));
+                }
+                None => {
+                    println!("Didn't work!");
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn access_logs(session: &Session) {
         let mut input: String = String::new();
         let now = Local::now().format("%d_%m_%Y");
+        let id = session.user();
 
         print!("{}", color::Fg(color::Green));
         println!("Welcome {}!", id);
         print!("{}", color::Fg(color::Reset));
+        report(
+            logs_api::move_date_tree(session),
+            "Unable to move logs out of the date tree",
+        );
 
         loop {
             print!("{}", color::Fg(color::Green));
             println!("Choose an option");
             print!("{}", color::Fg(color::Reset));
             print!("{}", color::Fg(color::Blue));
-            println!("{}.{} (For {})", '1', "Write new log", now);
-            println!("{}.{}", '2', "Browse all logs");
+            println!("1.Write new log (For {})", now);
+            println!("2.Browse all logs");
+            println!("3.Change password");
+            println!("4.Create recovery key");
+            println!("5.Key slots");
+            println!("6.Recompress logs");
             print!("{}", color::Fg(color::Reset));
 
             match read_input() {
@@ -162,76 +617,291 @@ pub mod menu {
 
             io::stdout().flush().unwrap();
             if input == "2" {
-                browse_logs(id);
+                browse_logs(session);
+            } else if input == "3" {
+                change_password(session.db(), id);
+            } else if input == "4" {
+                create_recovery_key(session);
+            } else if input == "5" {
+                key_slots(session);
+            } else if input == "6" {
+                recompress_logs(session);
             } else {
-                create_log(id, &now.to_string());
+                CryptoEditor::create_log(&now.to_string(), session);
+            }
+        }
+    }
+
+    // Saves the journal's compression setting and applies it to every log.
+    fn recompress_logs(session: &Session) {
+        let current = Compression::for_user(session.db(), session.user());
+        println!(
+            "Compression (deflate or none, empty keeps {}):",
+            current.name()
+        );
+        let compression = match read_input() {
+            Some(c) if c.trim().is_empty() => current,
+            Some(c) => match Compression::parse(&c) {
+                Some(compression) => compression,
+                None => {
+                    println!("Unknown compression: {}", c.trim());
+                    return;
+                }
+            },
+            None => {
+                println!("Didn't work!");
+                return;
+            }
+        };
+
+        if let Err(why) = profile::set_compression(session.db(), session.user(), compression) {
+            print!("{}", color::Fg(color::Red));
+            println!("Unable to save the compression setting: {}", why);
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+
+        println!("Recompressing logs...");
+        match logs_api::recompress_logs(session) {
+            Ok(count) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Recompressed {} logs.", count);
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to recompress logs: {}", why);
             }
         }
+        print!("{}", color::Fg(color::Reset));
     }
 
-    pub fn create_log(id: &str, now: &str) {
+    pub fn change_password(db: &Database, id: &str) {
+        if !check_login_throttle(db, id) {
+            return;
+        }
+        let (old_secret, keyfile) = match read_secret(db, id, "Current password") {
+            Some(c) => c,
+            None => {
+                println!("Didn't work!");
+                return;
+            }
+        };
+        let new_password = read_password_with_prompt("New password").unwrap_or_default();
+        let confirmation = read_password_with_prompt("New password again").unwrap_or_default();
+
+        if new_password.is_empty() || new_password != confirmation {
+            print!("{}", color::Fg(color::Red));
+            println!("Passwords do not match!");
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+
+        println!("Changing password...");
+        match key_chain::change_password(
+            db,
+            id,
+            &old_secret,
+            &new_password,
+            keyfile.as_deref().map(Vec::as_slice),
+        ) {
+            Ok(_) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Password changed.");
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to change password: {}", why);
+            }
+        }
+        print!("{}", color::Fg(color::Reset));
+    }
+
+    pub fn key_slots(session: &Session) {
+        let mut input: String = String::new();
+
         loop {
-            match read_password() {
-                Some(c) => {
-                    if let Ok(_) = key_chain::valid_auth(id, &c) {
-                        CryptoEditor::create_log(now, id, &c);
-                        break;
+            print!("{}", color::Fg(color::Green));
+            println!("Key slots of {}:", session.user());
+            print!("{}", color::Fg(color::Reset));
+            match key_chain::get_slots(session.db(), session.user()) {
+                Ok(slots) => {
+                    // Which slot was used is not shown: in a decoy it would
+                    // point at the duress slot.
+                    for (pos, slot) in slots.iter() {
+                        println!("  Slot {}: {}", pos, slot.kind.describe());
                     }
                 }
-                None => {
-                    println!("Didn't work!");
-                    break;
+                Err(why) => println!("Unable to read key slots: {}", why),
+            }
+
+            print!("{}", color::Fg(color::Blue));
+            println!("1.Add password");
+            println!("2.Add password and keyfile");
+            println!("3.Add recovery key");
+            println!("4.Add recovery key split into shares");
+            println!("5.Remove slot");
+            println!("6.Add duress password");
+            println!("7.Back");
+            print!("{}", color::Fg(color::Reset));
+
+            match read_input() {
+                Some(c) => input = c,
+                None => println!("Didn't work!"),
+            }
+
+            if input == "1" || input == "2" {
+                let password = read_password_with_prompt("New password").unwrap_or_default();
+                let confirmation =
+                    read_password_with_prompt("New password again").unwrap_or_default();
+                if password.is_empty() || password != confirmation {
+                    print!("{}", color::Fg(color::Red));
+                    println!("Passwords do not match!");
+                    print!("{}", color::Fg(color::Reset));
+                    continue;
+                }
+                let keyfile = if input == "2" {
+                    match read_keyfile("Keyfile path") {
+                        Some(keyfile) => Some(keyfile),
+                        None => continue,
+                    }
+                } else {
+                    None
+                };
+
+                println!("Adding key slot...");
+                match key_chain::add_password_slot(
+                    session,
+                    &password,
+                    keyfile.as_deref().map(Vec::as_slice),
+                ) {
+                    Ok(pos) => {
+                        print!("{}", color::Fg(color::Green));
+                        println!("Added slot {}.", pos);
+                    }
+                    Err(why) => {
+                        print!("{}", color::Fg(color::Red));
+                        println!("Unable to add key slot: {}", why);
+                    }
+                }
+                print!("{}", color::Fg(color::Reset));
+            } else if input == "3" {
+                create_recovery_key(session);
+            } else if input == "4" {
+                create_recovery_shares(session);
+            } else if input == "5" {
+                let pos = match read_with_prompt("Slot to remove") {
+                    Some(c) => match c.parse::<usize>() {
+                        Ok(pos) => pos,
+                        Err(_) => {
+                            println!("Invalid input. Choose one of the slots.");
+                            continue;
+                        }
+                    },
+                    None => continue,
+                };
+
+                match key_chain::remove_slot(session, pos) {
+                    Ok(_) => {
+                        print!("{}", color::Fg(color::Green));
+                        println!("Removed slot {}.", pos);
+                    }
+                    Err(why) => {
+                        print!("{}", color::Fg(color::Red));
+                        println!("Unable to remove key slot: {}", why);
+                    }
                 }
+                print!("{}", color::Fg(color::Reset));
+            } else if input == "6" {
+                add_duress_password(session);
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn add_duress_password(session: &Session) {
+        print!("{}", color::Fg(color::Green));
+        println!("A duress password opens an empty decoy journal instead of this one.");
+        print!("{}", color::Fg(color::Reset));
+        let password = read_password_with_prompt("Duress password").unwrap_or_default();
+        let confirmation = read_password_with_prompt("Duress password again").unwrap_or_default();
+        if password.is_empty() || password != confirmation {
+            print!("{}", color::Fg(color::Red));
+            println!("Passwords do not match!");
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+        print!("{}", color::Fg(color::Green));
+        println!("Remove every other key slot when it is used? This cannot be undone. (y/n)");
+        print!("{}", color::Fg(color::Reset));
+        let wipe = match read_input() {
+            Some(answer) => answer.trim().eq_ignore_ascii_case("y"),
+            None => false,
+        };
+
+        println!("Adding key slot...");
+        match key_chain::add_duress_slot(session, &password, wipe) {
+            Ok(pos) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Added slot {}.", pos);
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to add key slot: {}", why);
             }
         }
+        print!("{}", color::Fg(color::Reset));
     }
 
-    pub fn browse_logs(id: &str) {
+    pub fn browse_logs(session: &Session) {
+        let id = session.user();
         let mut input: String = String::new();
         let year: String;
         let month: String;
         let day: String;
-        let log: (usize, String);
+        let log: (String, String);
 
         print!("{}", color::Fg(color::Green));
         println!("Browsing {}'s logs", id);
         print!("{}", color::Fg(color::Reset));
 
-        if logs_api::has_logs(id) {
+        if logs_api::has_logs(session) {
+            check_logs(session);
             loop {
-                display_years(id);
+                let years = match display_years(session) {
+                    Some(years) => years,
+                    None => return,
+                };
                 match read_input() {
                     Some(c) => input = c,
                     None => println!("Didn't work!"),
                 }
 
-                if let Ok(years) = logs_api::get_years(id) {
-                    match process_option(input.clone(), years) {
-                        Some(y) => {
-                            year = y.1;
-                            print!("{}", color::Fg(color::Green));
-                            println!("Selected year: {}", year);
-                            print!("{}", color::Fg(color::Reset));
-                            break;
-                        }
-                        None => {
-                            println!("Invalid input. Choose one of the options.");
-                        }
+                match process_option(input.clone(), years) {
+                    Some(y) => {
+                        year = y.1;
+                        print!("{}", color::Fg(color::Green));
+                        println!("Selected year: {}", year);
+                        print!("{}", color::Fg(color::Reset));
+                        break;
+                    }
+                    None => {
+                        println!("Invalid input. Choose one of the options.");
                     }
                 }
             }
 
             loop {
-                display_months(id, &year);
+                let months = match display_months(session, &year) {
+                    Some(months) => months,
+                    None => return,
+                };
                 match read_input() {
                     Some(c) => input = c,
                     None => println!("Didn't work!"),
                 }
 
-                match process_option(
-                    input.clone(),
-                    logs_api::get_months(id, &year).expect("Unable to get years"),
-                ) {
+                match process_option(input.clone(), months) {
                     Some(m) => {
                         month = m.1;
                         print!("{}", color::Fg(color::Green));
@@ -245,16 +915,16 @@ pub mod menu {
                 }
             }
             loop {
-                display_days(id, &year, &month);
+                let days = match display_days(session, &year, &month) {
+                    Some(days) => days,
+                    None => return,
+                };
                 match read_input() {
                     Some(c) => input = c,
                     None => println!("Didn't work!"),
                 }
 
-                match process_option(
-                    input.clone(),
-                    logs_api::get_days(id, &year, &month).expect("Unable to get years"),
-                ) {
+                match process_option(input.clone(), days) {
                     Some(d) => {
                         day = d.1;
                         print!("{}", color::Fg(color::Green));
@@ -268,18 +938,23 @@ pub mod menu {
                 }
             }
             loop {
-                display_logs(id, &year, &month, &day);
+                let logs = match report(
+                    logs_api::get_day_logs(session, &year, &month, &day),
+                    "Unable to get logs",
+                ) {
+                    Some(logs) => logs,
+                    None => return,
+                };
+                let labels = describe_logs(&logs);
+                display_options(&labels);
                 match read_input() {
                     Some(c) => input = c,
                     None => println!("Didn't work!"),
                 }
 
-                match process_option(
-                    input.clone(),
-                    logs_api::get_day_logs(id, &year, &month, &day).expect("Unable to get logs"),
-                ) {
+                match process_option(input.clone(), labels) {
                     Some(l) => {
-                        log = l;
+                        log = (logs[l.0].id.clone(), l.1);
                         print!("{}", color::Fg(color::Green));
                         println!("Selected log: {}", log.1);
                         print!("{}", color::Fg(color::Reset));
@@ -291,10 +966,22 @@ pub mod menu {
                 }
             }
 
+            let date = format!("{}_{}_{}", day, month, year);
+            let sealed = logs_api::is_sealed(&date, session, &log.0).unwrap_or(false);
             print!("{}", color::Fg(color::Green));
             println!("Would you like to edit or read {}?", log.1);
             print!("{}", color::Fg(color::Reset));
-            let log_options = vec![String::from("Edit"), String::from("Read")];
+            let log_options = vec![
+                String::from("Edit"),
+                String::from("Read"),
+                String::from("Export"),
+                String::from("Revisions"),
+                if sealed {
+                    String::from("Remove its password")
+                } else {
+                    String::from("Seal with a password")
+                },
+            ];
             display_options(&log_options);
 
             match read_input() {
@@ -303,40 +990,23 @@ pub mod menu {
             }
 
             match process_option(input.clone(), log_options) {
-                Some(action) => loop {
-                    match read_password() {
-                        Some(c) => {
-                            if let Ok(_) = key_chain::valid_auth(id, &c) {
-                                match action.1.as_ref() {
-                                    "Edit" => {
-                                        CryptoEditor::edit_log(
-                                            &format!("{}_{}_{}", day, month, year),
-                                            id,
-                                            &c,
-                                            log.0,
-                                        );
-                                    }
-                                    "Read" => {
-                                        CryptoEditor::edit_log(
-                                            &format!("{}_{}_{}", day, month, year),
-                                            id,
-                                            &c,
-                                            log.0,
-                                        );
-                                    }
-                                    _ => (),
-                                }
-                                break;
+                Some(action) => match action.1.as_ref() {
+                    "Edit" | "Read" => {
+                        if !sealed {
+                            CryptoEditor::edit_log(&date, session, &log.0, None);
+                        } else if check_log_throttle(session) {
+                            if let Some(password) = read_password_with_prompt("Log password") {
+                                CryptoEditor::edit_log(&date, session, &log.0, Some(&password));
                             }
-                            print!("{}", color::Fg(color::Green));
-                            println!("Incorrect password!");
-                            print!("{}", color::Fg(color::Reset));
-                        }
-                        None => {
-                            println!("Didn't work!");
-                            break;
                         }
                     }
+                    "Export" => {
+                        export_log(&date, session, &log.0);
+                    }
+                    "Revisions" => log_revisions(&date, session, &log.0, sealed),
+                    "Seal with a password" => seal_log(&date, session, &log.0),
+                    "Remove its password" => unseal_log(&date, session, &log.0),
+                    _ => (),
                 },
                 None => {
                     println!("Invalid input. Choose one of the options.");
@@ -349,36 +1019,267 @@ pub mod menu {
         }
     }
 
-    fn display_options(options: &Vec<String>) {
-        print!("{}", color::Fg(color::Blue));
-        list_tools::utils::display_options(options.clone());
+    fn export_log(date: &str, session: &Session, id: &str) {
+        let path = match read_with_prompt("Export to") {
+            Some(path) if !path.is_empty() => path,
+            _ => return,
+        };
+        match log_export::export_log(date, session, id, &path) {
+            Ok(fingerprint) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Signed log written to {}", path);
+                println!("Signing key fingerprint: {}", fingerprint);
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to export log: {}", why);
+            }
+        }
+        print!("{}", color::Fg(color::Reset));
+    }
+
+    fn verify_export(db: &Database) {
+        let path = match read_with_prompt("Exported log") {
+            Some(path) if !path.is_empty() => path,
+            _ => return,
+        };
+        match log_export::verify_export(db, &path) {
+            Ok(export) => {
+                print!("{}", color::Fg(color::Green));
+                println!(
+                    "Valid signature: log {} of {} by {}",
+                    export.id, export.date, export.user
+                );
+                println!("Signing key fingerprint: {}", export.fingerprint);
+                print!("{}", color::Fg(color::Reset));
+                println!(
+                    "Compare the fingerprint with the one {} was given at export.",
+                    export.user
+                );
+                println!("A matching signature does not prove much when they differ.");
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("{}", why);
+            }
+        }
+        print!("{}", color::Fg(color::Reset));
+    }
+
+    fn check_logs(session: &Session) {
+        let problems = match logs_api::verify_logs(session) {
+            Ok(problems) => problems,
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to verify logs: {}", why);
+                print!("{}", color::Fg(color::Reset));
+                return;
+            }
+        };
+        if problems.is_empty() {
+            return;
+        }
+
+        print!("{}", color::Fg(color::Red));
+        println!("Warning: the history of these logs has been tampered with!");
+        for problem in problems.iter() {
+            println!("  {}", problem);
+        }
+        print!("{}", color::Fg(color::Reset));
+
+        print!("{}", color::Fg(color::Green));
+        println!("Accept the logs as they are now? (y/n)");
         print!("{}", color::Fg(color::Reset));
+        if let Some(answer) = read_input() {
+            if answer.trim().eq_ignore_ascii_case("y") {
+                if let Err(why) = logs_api::accept_logs(session) {
+                    print!("{}", color::Fg(color::Red));
+                    println!("Unable to accept logs: {}", why);
+                    print!("{}", color::Fg(color::Reset));
+                }
+            }
+        }
     }
 
-    fn display_logs(id: &str, year: &str, month: &str, day: &str) {
+    fn display_options(options: &[String]) {
         print!("{}", color::Fg(color::Blue));
-        let logs = logs_api::get_day_logs(id, year, month, day).expect("Unable to get logs");
-        list_tools::utils::display_options(logs);
+        list_tools::utils::display_options(options.to_vec());
+        print!("{}", color::Fg(color::Reset));
+    }
+
+    fn seal_log(date: &str, session: &Session, id: &str) {
+        let password = read_password_with_prompt("Log password").unwrap_or_default();
+        let confirmation = read_password_with_prompt("Log password again").unwrap_or_default();
+        if password.is_empty() || password != confirmation {
+            print!("{}", color::Fg(color::Red));
+            println!("Passwords do not match!");
+            print!("{}", color::Fg(color::Reset));
+            return;
+        }
+
+        println!("Sealing log...");
+        match logs_api::seal_log(date, session, id, &password) {
+            Ok(_) => {
+                print!("{}", color::Fg(color::Green));
+                println!("The log now also needs its own password to be opened.");
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to seal log: {}", why);
+            }
+        }
         print!("{}", color::Fg(color::Reset));
     }
-    fn display_days(id: &str, year: &str, month: &str) {
+
+    fn unseal_log(date: &str, session: &Session, id: &str) {
+        if !check_log_throttle(session) {
+            return;
+        }
+        let password = read_password_with_prompt("Log password").unwrap_or_default();
+        match logs_api::unseal_log(date, session, id, &password) {
+            Ok(_) => {
+                print!("{}", color::Fg(color::Green));
+                println!("Removed the password of the log.");
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to remove the password: {}", why);
+            }
+        }
+        print!("{}", color::Fg(color::Reset));
+    }
+
+    fn log_revisions(date: &str, session: &Session, id: &str, sealed: bool) {
+        let revisions = match logs_api::get_log_revisions(date, session, id) {
+            Ok(revisions) if !revisions.is_empty() => revisions,
+            Ok(_) => {
+                println!("The log has no earlier versions.");
+                return;
+            }
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("Unable to list revisions: {}", why);
+                print!("{}", color::Fg(color::Reset));
+                return;
+            }
+        };
+        if sealed && !check_log_throttle(session) {
+            return;
+        }
+        let password = if sealed {
+            match read_password_with_prompt("Log password") {
+                Some(password) => Some(password),
+                None => return,
+            }
+        } else {
+            None
+        };
+        let password = password.as_deref().map(String::as_str);
+
+        print!("{}", color::Fg(color::Green));
+        println!("Earlier versions, oldest first:");
+        print!("{}", color::Fg(color::Reset));
+        let revision_options: Vec<String> = revisions
+            .iter()
+            .enumerate()
+            .map(|(n, saved)| format!("Revision {}, saved {}", n + 1, format_time(*saved)))
+            .collect();
+        display_options(&revision_options);
+        let choice = read_input().and_then(|input| process_option(input, revision_options));
+        let revision = match choice {
+            Some(revision) => revision.0,
+            None => {
+                println!("Invalid input. Choose one of the options.");
+                return;
+            }
+        };
+
+        let actions = vec![String::from("View"), String::from("Restore")];
+        display_options(&actions);
+        match read_input().and_then(|input| process_option(input, actions)) {
+            Some(action) if action.1 == "View" => {
+                CryptoEditor::view_revision(date, session, id, revision, password)
+            }
+            Some(_) => {
+                match logs_api::restore_revision(date, session, id, revision, password) {
+                    Ok(_) => {
+                        print!("{}", color::Fg(color::Green));
+                        println!(
+                            "Restored revision {}. The version it replaced is kept as a revision.",
+                            revision + 1
+                        );
+                    }
+                    Err(why) => {
+                        print!("{}", color::Fg(color::Red));
+                        println!("Unable to restore revision: {}", why);
+                    }
+                }
+                print!("{}", color::Fg(color::Reset));
+            }
+            None => println!("Invalid input. Choose one of the options."),
+        }
+    }
+
+    fn format_time(saved: Option<u64>) -> String {
+        match saved.and_then(|saved| Local.timestamp_opt(saved as i64, 0).single()) {
+            Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => String::from("at an unknown time"),
+        }
+    }
+
+    fn describe_logs(logs: &[logs_api::LogSummary]) -> Vec<String> {
+        logs.iter()
+            .enumerate()
+            .map(|(n, log)| {
+                let mut label = format!("Log {}", n + 1);
+                if log.saved.is_some() {
+                    label.push_str(&format!(", saved {}", format_time(log.saved)));
+                }
+                if log.sealed {
+                    label.push_str(" (sealed)");
+                }
+                label
+            })
+            .collect()
+    }
+    // Prints why the journal could not be read, so the menu can go back
+    // instead of exiting.
+    fn report<T>(result: Result<T, io::Error>, what: &str) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(why) => {
+                print!("{}", color::Fg(color::Red));
+                println!("{}: {}", what, why);
+                print!("{}", color::Fg(color::Reset));
+                None
+            }
+        }
+    }
+
+    fn display_days(session: &Session, year: &str, month: &str) -> Option<Vec<String>> {
+        let days = report(
+            logs_api::get_days(session, year, month),
+            "Unable to get days",
+        )?;
         print!("{}", color::Fg(color::Blue));
-        let logs = logs_api::get_days(id, year, month).expect("Unable to get days");
-        list_tools::utils::display_options(logs);
+        list_tools::utils::display_options(days.clone());
         print!("{}", color::Fg(color::Reset));
+        Some(days)
     }
 
-    fn display_months(id: &str, year: &str) {
+    fn display_months(session: &Session, year: &str) -> Option<Vec<String>> {
+        let months = report(logs_api::get_months(session, year), "Unable to get months")?;
         print!("{}", color::Fg(color::Blue));
-        let logs = logs_api::get_months(id, year).expect("Unable to get months");
-        list_tools::utils::display_options(logs);
+        list_tools::utils::display_options(months.clone());
         print!("{}", color::Fg(color::Reset));
+        Some(months)
     }
 
-    fn display_years(id: &str) {
+    fn display_years(session: &Session) -> Option<Vec<String>> {
+        let years = report(logs_api::get_years(session), "Unable to get years")?;
         print!("{}", color::Fg(color::Blue));
-        let logs = logs_api::get_years(id).expect("Unable to get years");
-        list_tools::utils::display_options(logs);
+        list_tools::utils::display_options(years.clone());
         print!("{}", color::Fg(color::Reset));
+        Some(years)
     }
 }
@@ -0,0 +1,69 @@
+// Keeps decrypted logs, passwords and keys from leaving the process by other
+// routes than the journal itself: no core dumps, and memory locked so it is
+// not written to swap. Both are best effort; an unprivileged process may not
+// be allowed to lock all of its memory.
+pub mod process {
+    use std::io::Error;
+
+    #[cfg(unix)]
+    pub fn disable_core_dumps() -> Result<(), Error> {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        #[cfg(target_os = "linux")]
+        if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // Locking future allocations as well only happens when the memlock limit
+    // is unbounded; otherwise an allocation past the limit would fail. The
+    // memory in use is still locked then, but since everything allocated
+    // later, decrypted logs included, may be swapped, that is reported as an
+    // error too.
+    #[cfg(unix)]
+    pub fn lock_memory() -> Result<(), Error> {
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut flags = libc::MCL_CURRENT;
+        if limit.rlim_max == libc::RLIM_INFINITY {
+            limit.rlim_cur = libc::RLIM_INFINITY;
+            if unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) } == 0 {
+                flags |= libc::MCL_FUTURE;
+            }
+        }
+        if unsafe { libc::mlockall(flags) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        if flags & libc::MCL_FUTURE == 0 {
+            return Err(Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "the memlock limit only allows locking the memory already in use",
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn disable_core_dumps() -> Result<(), Error> {
+        Err(Error::new(std::io::ErrorKind::Unsupported, "Not supported"))
+    }
+
+    #[cfg(not(unix))]
+    pub fn lock_memory() -> Result<(), Error> {
+        Err(Error::new(std::io::ErrorKind::Unsupported, "Not supported"))
+    }
+}
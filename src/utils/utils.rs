@@ -1,62 +1,191 @@
 pub mod file_system {
     use std::env;
     use std::fs;
-    use std::io;
+    use std::io::prelude::*;
     use std::io::{Error, ErrorKind};
-    pub fn read_dir_to_string(dir_path: String) -> Result<Vec<String>, io::Error> {
-        let mut files: Vec<String> = Vec::new();
-        for entry in fs::read_dir(dir_path)? {
-            let entry = entry?;
-            let file_name = match entry.file_name().into_string() {
-                Err(_) => continue,
-                Ok(str) => str,
-            };
-            files.push(file_name);
-        }
-        Ok(files)
-    }
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
 
     pub fn generate_log_path(logs_dir: &str, n: usize) -> String {
         let filename = format!("log_{}.dat", n);
         generate_path(&[logs_dir, "/", &filename])
     }
 
-    pub fn generate_salt_path(id: &str) -> Option<String> {
+    pub fn generate_user_logs_root(ids_dir: &str, user: &str) -> String {
+        generate_path(&[ids_dir, "/", user])
+    }
+
+    pub fn generate_index_path(ids_dir: &str, user: &str) -> String {
+        generate_path(&[&generate_user_logs_root(ids_dir, user), "/index.dat"])
+    }
+
+    pub fn generate_manifest_path(ids_dir: &str, user: &str) -> String {
+        generate_path(&[&generate_user_logs_root(ids_dir, user), "/manifest.dat"])
+    }
+
+    pub fn generate_blobs_dir(ids_dir: &str, user: &str) -> String {
+        generate_path(&[&generate_user_logs_root(ids_dir, user), "/blobs"])
+    }
+
+    pub fn generate_blob_path(ids_dir: &str, user: &str, blob: &str) -> String {
+        let filename = format!("{}.dat", blob);
+        generate_path(&[&generate_blobs_dir(ids_dir, user), "/", &filename])
+    }
+
+    pub fn parse_log_pos(filename: &str) -> Option<usize> {
+        filename
+            .strip_prefix("log_")
+            .and_then(|name| name.strip_suffix(".dat"))
+            .and_then(|pos| pos.parse::<usize>().ok())
+    }
+
+    pub fn generate_key_dir(keys_dir: &str, id: &str) -> String {
+        generate_path(&[keys_dir, "/", id])
+    }
+
+    pub fn generate_salt_path(keys_dir: &str, id: &str) -> String {
         let filename = format!("{}.txt", id);
-        if let Ok(dir) = get_env_var(&"DATABASE_KEYS_DIR") {
-            return Some(generate_path(&[&dir, "/", id, "/", &filename]));
-        }
-        None
+        generate_path(&[&generate_key_dir(keys_dir, id), "/", &filename])
+    }
+
+    pub fn generate_pass_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/pass.txt"])
+    }
+
+    pub fn generate_master_key_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/master.key"])
     }
 
-    pub fn generate_pass_path(id: &str) -> Option<String> {
-        if let Ok(dir) = get_env_var(&"DATABASE_KEYS_DIR") {
-            return Some(generate_path(&[&dir, "/", id, "/", "pass.txt"]));
+    pub fn generate_recovery_key_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/recovery.key"])
+    }
+
+    pub fn generate_checkpoint_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/manifest.head"])
+    }
+
+    pub fn generate_signing_key_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/signing.key"])
+    }
+
+    pub fn generate_public_key_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/signing.pub"])
+    }
+
+    pub fn generate_throttle_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/throttle.txt"])
+    }
+
+    pub fn generate_log_throttle_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/log_throttle.txt"])
+    }
+
+    pub fn generate_profile_path(keys_dir: &str, id: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/profile.txt"])
+    }
+
+    pub fn generate_slots_dir(keys_dir: &str, id: &str, suffix: &str) -> String {
+        generate_path(&[&generate_key_dir(keys_dir, id), "/slots", suffix])
+    }
+
+    pub fn generate_slot_path(slots_dir: &str, n: usize) -> String {
+        let filename = format!("{}.slot", n);
+        generate_path(&[slots_dir, "/", &filename])
+    }
+
+    pub fn parse_slot_pos(filename: &str) -> Option<usize> {
+        filename
+            .strip_suffix(".slot")
+            .and_then(|pos| pos.parse::<usize>().ok())
+    }
+
+    pub fn write_file_atomic(file_path: &str, contents: &[u8]) -> Result<(), Error> {
+        write_file_atomic_with(file_path, |file| file.write_all(contents))
+    }
+
+    // Like `write_file_atomic`, for contents that are written piece by piece.
+    pub fn write_file_atomic_with<F>(file_path: &str, write: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut fs::File) -> Result<(), Error>,
+    {
+        let path = Path::new(file_path);
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return Err(Error::new(ErrorKind::InvalidInput, "Invalid file path")),
+        };
+        let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+        let mut file = fs::File::create(&temp_path)?;
+        if let Err(why) = write(&mut file) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(why);
         }
-        None
+        file.sync_all()?;
+        fs::rename(temp_path, path)?;
+        sync_parent_dir(path)
     }
 
-    pub fn generate_key_dir(id: &str) -> Result<(), Error> {
-        if let Ok(dir) = get_env_var(&"DATABASE_KEYS_DIR") {
-            let path = generate_path(&[&dir, "/", id, "/"]);
-            fs::create_dir_all(path)?;
+    // Makes the creation, rename or removal of `path` itself durable, which
+    // syncing the file does not. Outside Unix directories cannot be opened to
+    // be synced, and this does nothing.
+    pub fn sync_parent_dir(path: &Path) -> Result<(), Error> {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        #[cfg(unix)]
+        fs::File::open(parent)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = parent;
+
+        Ok(())
+    }
 
+    // Creates `dir` and any missing parents so that they survive a crash.
+    pub fn create_dir_all_durable(dir: &Path) -> Result<(), Error> {
+        if dir.as_os_str().is_empty() || dir.is_dir() {
             return Ok(());
         }
+        if let Some(parent) = dir.parent() {
+            create_dir_all_durable(parent)?;
+        }
 
-        Err(Error::new(
-            ErrorKind::NotFound,
-            "DATABASE_KEYS_DIR env variable Not Found",
-        ))
+        match fs::create_dir(dir) {
+            Err(why) if why.kind() != ErrorKind::AlreadyExists => return Err(why),
+            _ => {}
+        }
+        sync_parent_dir(dir)
     }
 
     pub fn generate_path(path: &[&str]) -> String {
         let mut complete_path = String::new();
         for part in path {
-            complete_path.push_str(&part);
+            complete_path.push_str(part);
         }
         complete_path
     }
+    // Seconds since the epoch, or 0 when the clock is set before it.
+    pub fn unix_time() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0)
+    }
+
+    pub fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
+    }
+
     pub fn format_txt_filename(filename: &str) -> &str {
         &filename[0..filename.len() - 4]
     }